@@ -0,0 +1,192 @@
+use crate::{error::Error, *};
+use serde_json::Value;
+use std::{
+    io::{self, BufReader, BufWriter},
+    panic,
+};
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    sync::mpsc,
+    task::{self, JoinError, JoinHandle},
+};
+use tokio_util::io::SyncIoBridge;
+
+/// Turn a task that did not complete into an error, carrying on with the
+/// panic of one that panicked.
+fn join_error(error: JoinError) -> Error {
+    match error.try_into_panic() {
+        Ok(payload) => panic::resume_unwind(payload),
+        Err(error) => Error::IoError(io::Error::other(error)),
+    }
+}
+
+/// Run a conversion reading from `reader` and writing to `writer` on the
+/// blocking thread pool, so that it neither blocks the executor nor needs
+/// the whole input in memory, and return the writer once it is flushed.
+async fn convert<R, W, F>(reader: R, writer: W, convert: F) -> Result<W, Error>
+where
+    R: AsyncRead + Unpin + Send + 'static,
+    W: AsyncWrite + Unpin + Send + 'static,
+    F: FnOnce(BufReader<SyncIoBridge<R>>, &mut BufWriter<SyncIoBridge<W>>) -> Result<(), Error>
+        + Send
+        + 'static,
+{
+    task::spawn_blocking(move || {
+        let mut writer = BufWriter::new(SyncIoBridge::new(writer));
+        convert(BufReader::new(SyncIoBridge::new(reader)), &mut writer)?;
+
+        writer
+            .into_inner()
+            .map(SyncIoBridge::into_inner)
+            .map_err(|e| Error::IoError(e.into_error()))
+    })
+    .await
+    .map_err(join_error)?
+}
+
+/// Convert XML read from `reader` to JSON text written to `writer`,
+/// returning the writer. Like [`xml_to_json_writer`], this writes each node
+/// as soon as it is read.
+pub async fn xml_to_json_writer_async<R, W>(reader: R, writer: W) -> Result<W, Error>
+where
+    R: AsyncRead + Unpin + Send + 'static,
+    W: AsyncWrite + Unpin + Send + 'static,
+{
+    let options = ReadOptions {
+        document_order: true,
+        ..ReadOptions::default()
+    };
+
+    xml_to_json_writer_with_options_async(reader, writer, &options).await
+}
+
+/// Convert XML read from `reader` to JSON text written to `writer` using the
+/// given options, returning the writer. Like the other async functions, it
+/// must be called from within a Tokio runtime, where the conversion runs on
+/// the blocking thread pool. See [`xml_to_json_writer_with_options`].
+pub async fn xml_to_json_writer_with_options_async<R, W>(
+    reader: R,
+    writer: W,
+    options: &ReadOptions,
+) -> Result<W, Error>
+where
+    R: AsyncRead + Unpin + Send + 'static,
+    W: AsyncWrite + Unpin + Send + 'static,
+{
+    let options = options.clone();
+
+    convert(reader, writer, move |reader, writer| {
+        xml_to_json_writer_with_options(reader, writer, &options)
+    })
+    .await
+}
+
+/// Convert JSON read from `reader` to XML written to `writer`, returning the
+/// writer. See [`json_to_xml_writer`].
+pub async fn json_to_xml_writer_async<R, W>(
+    reader: R,
+    writer: W,
+    options: &WriteOptions,
+) -> Result<W, Error>
+where
+    R: AsyncRead + Unpin + Send + 'static,
+    W: AsyncWrite + Unpin + Send + 'static,
+{
+    let options = options.clone();
+
+    convert(reader, writer, move |reader, writer| {
+        json_to_xml_writer(reader, writer, &options)
+    })
+    .await
+}
+
+/// Send each value to `sender`, stopping once nothing receives them.
+fn send_all<I>(values: I, sender: mpsc::Sender<Result<Value, Error>>)
+where
+    I: Iterator<Item = Result<Value, Error>>,
+{
+    for value in values {
+        if sender.blocking_send(value).is_err() {
+            break;
+        }
+    }
+}
+
+/// The values read from an `AsyncRead`, created by [`records_async`],
+/// [`records_with_options_async`] or [`xml_documents_to_json_async`].
+///
+/// They are read ahead by one on the blocking thread pool of the Tokio
+/// runtime it is created in, and reading stops when this is dropped.
+pub struct AsyncValues {
+    receiver: mpsc::Receiver<Result<Value, Error>>,
+    task: Option<JoinHandle<()>>,
+}
+
+impl AsyncValues {
+    /// Read values with `read` on the blocking thread pool, which sends
+    /// each one as soon as it is read.
+    fn spawn<R, F>(reader: R, read: F) -> Self
+    where
+        R: AsyncRead + Unpin + Send + 'static,
+        F: FnOnce(BufReader<SyncIoBridge<R>>, mpsc::Sender<Result<Value, Error>>) + Send + 'static,
+    {
+        let (sender, receiver) = mpsc::channel(1);
+        let task =
+            task::spawn_blocking(move || read(BufReader::new(SyncIoBridge::new(reader)), sender));
+
+        AsyncValues {
+            receiver,
+            task: Some(task),
+        }
+    }
+
+    /// Wait for the next value, or `None` once there are no more. Ends after
+    /// the first error, like the iterators it stands for.
+    pub async fn next(&mut self) -> Option<Result<Value, Error>> {
+        if let Some(value) = self.receiver.recv().await {
+            return Some(value);
+        }
+
+        match self.task.take()?.await {
+            Ok(()) => None,
+            Err(error) => Some(Err(join_error(error))),
+        }
+    }
+}
+
+/// Read the elements at `path` from `reader` one at a time. See
+/// [`records_with_options`].
+pub fn records_async<R>(reader: R, path: &str) -> AsyncValues
+where
+    R: AsyncRead + Unpin + Send + 'static,
+{
+    records_with_options_async(reader, path, &ReadOptions::default())
+}
+
+/// Read the elements at `path` from `reader` one at a time using the given
+/// options. The ancestors of the records are not available. See
+/// [`records_with_options`].
+pub fn records_with_options_async<R>(reader: R, path: &str, options: &ReadOptions) -> AsyncValues
+where
+    R: AsyncRead + Unpin + Send + 'static,
+{
+    let path = path.to_owned();
+    let options = options.clone();
+
+    AsyncValues::spawn(reader, move |reader, sender| {
+        send_all(records_with_options(reader, &path, &options), sender)
+    })
+}
+
+/// Read a stream of concatenated XML documents from `reader`, yielding one
+/// JSON value per document. See [`xml_documents_to_json`].
+pub fn xml_documents_to_json_async<R>(reader: R, options: &ReadOptions) -> AsyncValues
+where
+    R: AsyncRead + Unpin + Send + 'static,
+{
+    let options = options.clone();
+
+    AsyncValues::spawn(reader, move |reader, sender| {
+        send_all(xml_documents_to_json(reader, &options), sender)
+    })
+}
@@ -0,0 +1,304 @@
+use crate::{
+    entities::Entities, error::Error, recover::Recovery, to_json::normalize_attribute_value,
+    to_xml::value_to_xml, *,
+};
+use quick_xml::{events::*, Reader};
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+
+const XML_NAMESPACE: &str = "http://www.w3.org/XML/1998/namespace";
+
+type Namespaces = BTreeMap<String, String>;
+
+struct Scope {
+    /// Namespaces declared on this element or its ancestors in the input.
+    in_scope: Namespaces,
+    /// Namespaces declared on this element or its ancestors in the output.
+    rendered: Namespaces,
+}
+
+fn split_name(name: &str) -> (&str, &str) {
+    match name.find(':') {
+        Some(i) => (&name[..i], &name[i + 1..]),
+        None => ("", name),
+    }
+}
+
+/// Normalize `\r\n` and lone `\r` to `\n`, as an XML processor does before
+/// parsing.
+fn normalize_line_endings(xml: &str) -> String {
+    xml.replace("\r\n", "\n").replace('\r', "\n")
+}
+
+fn escape_text(text: &str, out: &mut String) {
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '\r' => out.push_str("&#xD;"),
+            _ => out.push(c),
+        }
+    }
+}
+
+fn escape_attribute_value(value: &str, out: &mut String) {
+    for c in value.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '"' => out.push_str("&quot;"),
+            '\t' => out.push_str("&#x9;"),
+            '\n' => out.push_str("&#xA;"),
+            '\r' => out.push_str("&#xD;"),
+            _ => out.push(c),
+        }
+    }
+}
+
+/// The prefixes whose declarations are considered for an element: every
+/// namespace in scope for Canonical XML 1.0, and only the visibly utilized
+/// ones plus the inclusive prefix list for Exclusive XML Canonicalization.
+fn namespace_candidates<'b>(
+    options: &CanonicalOptions,
+    in_scope: &'b Namespaces,
+    element_prefix: &'b str,
+    attributes: &'b [(String, String)],
+) -> Vec<&'b str> {
+    if !options.exclusive {
+        return in_scope.keys().map(|prefix| prefix.as_str()).collect();
+    }
+
+    let mut candidates = vec![element_prefix];
+
+    for (name, _) in attributes {
+        let (prefix, _) = split_name(name);
+
+        if !prefix.is_empty() {
+            candidates.push(prefix);
+        }
+    }
+
+    for prefix in options.inclusive_prefixes.iter() {
+        let prefix = match prefix.as_str() {
+            "#default" => "",
+            prefix => prefix,
+        };
+
+        if let Some((prefix, _)) = in_scope.get_key_value(prefix) {
+            candidates.push(prefix);
+        }
+    }
+
+    candidates.sort_unstable();
+    candidates.dedup();
+    candidates
+}
+
+fn write_start(
+    start: &BytesStart,
+    options: &CanonicalOptions,
+    entities: &mut Entities,
+    stack: &mut Vec<Scope>,
+    out: &mut String,
+) -> Result<(), Error> {
+    let (mut in_scope, mut rendered) = match stack.last() {
+        Some(scope) => (scope.in_scope.clone(), scope.rendered.clone()),
+        None => (Namespaces::new(), Namespaces::new()),
+    };
+    let mut attributes = vec![];
+
+    for attribute in start.attributes() {
+        let attribute = attribute.map_err(Error::XmlQuickXmlError)?;
+        let name = bytes_to_string(attribute.key)?;
+        let value = entities.unescape(
+            &bytes_to_string(&normalize_attribute_value(&attribute.value))?,
+            0,
+            &mut Recovery::strict(),
+        )?;
+
+        if name == "xmlns" {
+            in_scope.insert(String::new(), value);
+        } else if let Some(prefix) = name.strip_prefix("xmlns:") {
+            in_scope.insert(prefix.to_owned(), value);
+        } else {
+            attributes.push((name, value));
+        }
+    }
+
+    let name = bytes_to_string(start.name())?;
+    let (element_prefix, _) = split_name(&name);
+    let mut declarations = vec![];
+
+    for prefix in namespace_candidates(options, &in_scope, element_prefix, &attributes) {
+        if prefix == "xml" {
+            continue;
+        }
+
+        let uri = in_scope.get(prefix).map_or("", |uri| uri.as_str());
+        let declare = match rendered.get(prefix) {
+            Some(rendered) => rendered != uri,
+            None => !uri.is_empty(),
+        };
+
+        if declare {
+            declarations.push((prefix.to_owned(), uri.to_owned()));
+            rendered.insert(prefix.to_owned(), uri.to_owned());
+        }
+    }
+
+    let mut attributes = attributes
+        .into_iter()
+        .map(|(name, value)| {
+            let (prefix, local) = split_name(&name);
+            let uri = match prefix {
+                "" => "",
+                "xml" => XML_NAMESPACE,
+                prefix => in_scope.get(prefix).map_or("", |uri| uri.as_str()),
+            };
+
+            ((uri.to_owned(), local.to_owned()), name, value)
+        })
+        .collect::<Vec<_>>();
+    attributes.sort_by(|a, b| a.0.cmp(&b.0));
+
+    out.push('<');
+    out.push_str(&name);
+
+    for (prefix, uri) in declarations {
+        out.push_str(" xmlns");
+
+        if !prefix.is_empty() {
+            out.push(':');
+            out.push_str(&prefix);
+        }
+
+        out.push_str("=\"");
+        escape_attribute_value(&uri, out);
+        out.push('"');
+    }
+
+    for (_, name, value) in attributes {
+        out.push(' ');
+        out.push_str(&name);
+        out.push_str("=\"");
+        escape_attribute_value(&value, out);
+        out.push('"');
+    }
+
+    out.push('>');
+    stack.push(Scope { in_scope, rendered });
+
+    Ok(())
+}
+
+/// Write a comment or processing instruction, separating it from the
+/// document element with a line break when it appears outside of it.
+fn write_outside_node(node: &str, depth: usize, after_root: bool, out: &mut String) {
+    if depth == 0 && after_root {
+        out.push('\n');
+    }
+
+    out.push_str(node);
+
+    if depth == 0 && !after_root {
+        out.push('\n');
+    }
+}
+
+/// Convert an XML string to its canonical form.
+///
+/// Implements Canonical XML 1.0 or, with `CanonicalOptions::exclusive`,
+/// Exclusive XML Canonicalization for whole documents. Only the entity
+/// declarations of the internal DTD subset are processed, so attribute
+/// defaults and types declared in a DTD are not applied.
+pub fn canonicalize_xml(xml: &str, options: &CanonicalOptions) -> Result<String, Error> {
+    let xml = normalize_line_endings(xml);
+    let mut reader = Reader::from_str(&xml);
+    reader.expand_empty_elements(true);
+
+    let mut buf = vec![];
+    let mut out = String::new();
+    let mut stack = vec![];
+    let mut after_root = false;
+    let custom_entities = HashMap::new();
+    let mut entities = Entities::new(
+        &custom_entities,
+        false,
+        ParseLimits::default().max_entity_expansion,
+    );
+
+    loop {
+        match reader.read_event(&mut buf) {
+            Ok(Event::Start(ref e)) => {
+                write_start(e, options, &mut entities, &mut stack, &mut out)?
+            }
+            Ok(Event::End(ref e)) => {
+                stack.pop();
+                out.push_str("</");
+                out.push_str(&bytes_to_string(e.name())?);
+                out.push('>');
+
+                if stack.is_empty() {
+                    after_root = true;
+                }
+            }
+            Ok(Event::Text(ref e)) => {
+                if !stack.is_empty() {
+                    escape_text(
+                        &entities.unescape(
+                            &bytes_to_string(e.escaped())?,
+                            0,
+                            &mut Recovery::strict(),
+                        )?,
+                        &mut out,
+                    );
+                }
+            }
+            Ok(Event::CData(ref e)) => escape_text(&bytes_to_string(e)?, &mut out),
+            Ok(Event::Comment(ref e)) => {
+                if options.with_comments {
+                    let comment = format!("<!--{}-->", bytes_to_string(e)?);
+                    write_outside_node(&comment, stack.len(), after_root, &mut out);
+                }
+            }
+            Ok(Event::PI(ref e)) => {
+                let pi = bytes_to_string(e)?;
+                let (target, data) = match pi.find(|c: char| c.is_ascii_whitespace()) {
+                    Some(i) => (
+                        &pi[..i],
+                        pi[i..].trim_start_matches(|c: char| c.is_ascii_whitespace()),
+                    ),
+                    None => (pi.as_str(), ""),
+                };
+                let pi = match data.is_empty() {
+                    true => format!("<?{}?>", target),
+                    false => format!("<?{} {}?>", target, data),
+                };
+
+                write_outside_node(&pi, stack.len(), after_root, &mut out);
+            }
+            Ok(Event::DocType(ref e)) => {
+                entities.declare_internal_subset(&bytes_to_string(e.escaped())?)?
+            }
+            Ok(Event::Decl(_)) | Ok(Event::Empty(_)) => {}
+            Ok(Event::Eof) => {
+                if !stack.is_empty() {
+                    return Err(Error::XmlParseUnexpectedEof);
+                }
+
+                break;
+            }
+            Err(e) => return Err(Error::XmlQuickXmlError(e)),
+        }
+
+        buf.clear();
+    }
+
+    Ok(out)
+}
+
+/// Convert a JSON value following the jxon conventions to canonical XML.
+pub fn canonicalize_json(value: &Value, options: &CanonicalOptions) -> Result<String, Error> {
+    canonicalize_xml(&value_to_xml(value, &WriteOptions::default())?, options)
+}
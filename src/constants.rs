@@ -1,3 +1,7 @@
 pub const ATTRIBUTE_START_CHARACTER: char = '$';
 pub const TEXT_CHARACTER: char = '_';
 pub const DECL_STRING: &str = "#";
+pub const EMPTY_STYLE_STRING: &str = "#empty";
+pub const QUOTES_STRING: &str = "#quotes";
+pub const SELF_CLOSING_STRING: &str = "self-closing";
+pub const EXPANDED_STRING: &str = "expanded";
@@ -0,0 +1,439 @@
+use crate::{
+    constants::*,
+    entities::Entities,
+    error::Error,
+    events::{build_document, JxonEvent},
+    format::{ElementStyle, Formatter},
+    recover::Recovery,
+    to_json::{self, apply_whitespace, check_element_limits},
+    to_xml::{self, escape_attribute_value},
+    *,
+};
+use quick_xml::{
+    events::{BytesEnd, BytesStart, BytesText, Event},
+    Reader,
+};
+use serde_json::{Map, Value};
+use std::borrow::Cow;
+
+/// A node inside an element or at the top level of a document.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Node {
+    Element(Element),
+    /// Text, with its references replaced.
+    Text(String),
+    /// The content of a CDATA section.
+    CData(String),
+    /// The content of a comment.
+    Comment(String),
+    /// The content of a processing instruction, starting with its target.
+    PI(String),
+}
+
+/// An element with its attributes and content in document order.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Element {
+    pub name: String,
+    /// The name and value of each attribute, with its references replaced.
+    pub attributes: Vec<(String, String)>,
+    pub children: Vec<Node>,
+}
+
+impl Element {
+    /// An element without attributes or content.
+    pub fn new(name: &str) -> Self {
+        Element {
+            name: name.to_owned(),
+            attributes: vec![],
+            children: vec![],
+        }
+    }
+
+    /// The value of the attribute named `name`.
+    pub fn attribute(&self, name: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// The elements directly inside this one.
+    pub fn elements(&self) -> impl Iterator<Item = &Element> {
+        self.children.iter().filter_map(|child| match child {
+            Node::Element(element) => Some(element),
+            _ => None,
+        })
+    }
+}
+
+/// Drops the nodes inside an element keeping those left to drop on an
+/// explicit stack, so that deep elements do not overflow the native one.
+impl Drop for Element {
+    fn drop(&mut self) {
+        let mut nodes = std::mem::take(&mut self.children);
+
+        while let Some(node) = nodes.pop() {
+            if let Node::Element(mut element) = node {
+                nodes.append(&mut element.children);
+            }
+        }
+    }
+}
+
+/// An XML document as an ordered tree, which unlike the JSON form keeps
+/// comments, processing instructions, CDATA sections and the order of all of
+/// its nodes.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Document {
+    pub declaration: Option<Declaration>,
+    /// What follows `<!DOCTYPE` in the document type declaration, including
+    /// any internal subset. It is written after the XML declaration.
+    pub doctype: Option<String>,
+    /// The nodes at the top level, such as the root element and the comments
+    /// and whitespace around it.
+    pub children: Vec<Node>,
+}
+
+/// Builds a document from quick-xml events, keeping the elements that are
+/// not complete yet on an explicit stack.
+struct TreeBuilder<'o> {
+    document: Document,
+    stack: Vec<Element>,
+    entities: Entities<'o>,
+    recovery: Recovery,
+    options: &'o ReadOptions,
+    /// The number of elements read, counting towards the limits.
+    elements: usize,
+}
+
+impl<'o> TreeBuilder<'o> {
+    fn new(options: &'o ReadOptions) -> Self {
+        TreeBuilder {
+            document: Document::default(),
+            stack: vec![],
+            entities: Entities::new(
+                &options.entities,
+                false,
+                options.limits.max_entity_expansion,
+            ),
+            recovery: Recovery::strict(),
+            options,
+            elements: 0,
+        }
+    }
+
+    fn unescape(&mut self, raw: &[u8]) -> Result<String, Error> {
+        self.entities
+            .unescape(&bytes_to_string(raw)?, 0, &mut self.recovery)
+    }
+
+    fn push(&mut self, node: Node) {
+        match self.stack.last_mut() {
+            Some(parent) => parent.children.push(node),
+            None => self.document.children.push(node),
+        }
+    }
+
+    fn open(&mut self, start: &BytesStart) -> Result<Element, Error> {
+        check_element_limits(
+            start,
+            self.stack.len() + 1,
+            &mut self.elements,
+            self.options,
+        )?;
+
+        let mut element = Element::new(&bytes_to_string(start.name())?);
+
+        for attribute in start.attributes() {
+            let attribute = attribute.map_err(Error::XmlQuickXmlError)?;
+            let value = self.unescape(&attribute.value)?;
+
+            element
+                .attributes
+                .push((bytes_to_string(attribute.key)?, value));
+        }
+
+        Ok(element)
+    }
+
+    fn event(&mut self, event: Event) -> Result<(), Error> {
+        match event {
+            Event::Start(start) => {
+                let element = self.open(&start)?;
+                self.stack.push(element);
+            }
+            Event::Empty(start) => {
+                let element = self.open(&start)?;
+                self.push(Node::Element(element));
+            }
+            Event::End(end) => {
+                let element = match self.stack.pop() {
+                    Some(element) => element,
+                    None => {
+                        return Err(Error::XmlParseUnexpectedEndTag(bytes_to_string(
+                            end.name(),
+                        )?))
+                    }
+                };
+                self.push(Node::Element(element));
+            }
+            Event::Text(text) => {
+                // The reader yields empty text between adjacent markup.
+                if !text.is_empty() {
+                    let text = self.unescape(text.escaped())?;
+
+                    if self
+                        .options
+                        .limits
+                        .max_text_length
+                        .is_some_and(|max| text.chars().count() > max)
+                    {
+                        return Err(Error::XmlParseTextLimitExceeded);
+                    }
+
+                    self.push(Node::Text(text));
+                }
+            }
+            Event::CData(cdata) => self.push(Node::CData(bytes_to_string(&cdata)?)),
+            Event::Comment(comment) => self.push(Node::Comment(bytes_to_string(&comment)?)),
+            Event::PI(pi) => self.push(Node::PI(bytes_to_string(&pi)?)),
+            Event::Decl(decl) => {
+                let decl = Value::Object(to_json::read_decl(&decl)?);
+                self.document.declaration =
+                    Some(serde_json::from_value(decl).map_err(Error::JsonParseError)?);
+            }
+            Event::DocType(doctype) => {
+                let doctype = bytes_to_string(doctype.escaped())?;
+                self.entities.declare_internal_subset(&doctype)?;
+                self.document.doctype = Some(doctype.trim().to_owned());
+            }
+            Event::Eof => {}
+        }
+
+        Ok(())
+    }
+
+    fn finish(self) -> Result<Document, Error> {
+        match self.stack.is_empty() {
+            true => Ok(self.document),
+            false => Err(Error::XmlParseUnexpectedEof),
+        }
+    }
+}
+
+/// What is reached while walking a document.
+enum Visit<'d> {
+    Node(&'d Node),
+    /// The end of an element with content, once all of it was visited.
+    End(&'d Element),
+}
+
+impl Document {
+    /// Read an XML document, keeping every node. References to the
+    /// predefined entities, characters and the entities declared in the
+    /// internal subset are replaced. See [`Document::parse_with_options`].
+    pub fn parse(xml: &str) -> Result<Document, Error> {
+        Document::parse_with_options(xml, &ReadOptions::default())
+    }
+
+    /// Read an XML document using the given options, of which only the
+    /// limits and the entities apply, as every node is kept as is.
+    pub fn parse_with_options(xml: &str, options: &ReadOptions) -> Result<Document, Error> {
+        let mut reader = Reader::from_str(xml);
+        let mut buf = vec![];
+        let mut builder = TreeBuilder::new(options);
+
+        loop {
+            buf.clear();
+
+            let event = reader.read_event(&mut buf);
+
+            if options
+                .limits
+                .max_bytes
+                .is_some_and(|max| reader.buffer_position() > max)
+            {
+                return Err(Error::XmlParseSizeLimitExceeded);
+            }
+
+            match event {
+                Ok(Event::Eof) => return builder.finish(),
+                Ok(event) => builder.event(event)?,
+                Err(e) => return Err(Error::XmlQuickXmlError(e)),
+            }
+        }
+    }
+
+    /// The first element at the top level.
+    pub fn root(&self) -> Option<&Element> {
+        self.children.iter().find_map(|child| match child {
+            Node::Element(element) => Some(element),
+            _ => None,
+        })
+    }
+
+    /// Walk the nodes of the document in order, keeping the elements being
+    /// walked on an explicit stack rather than the native one so that deep
+    /// documents do not overflow it.
+    fn walk<'d>(
+        &'d self,
+        mut visit: impl FnMut(Visit<'d>) -> Result<(), Error>,
+    ) -> Result<(), Error> {
+        // The children left in each element being walked, or the document.
+        let mut stack = vec![(self.children.iter(), None)];
+
+        while let Some((children, parent)) = stack.last_mut() {
+            match children.next() {
+                Some(node) => {
+                    visit(Visit::Node(node))?;
+
+                    if let Node::Element(element) = node {
+                        if !element.children.is_empty() {
+                            stack.push((element.children.iter(), Some(element)));
+                        }
+                    }
+                }
+                None => {
+                    if let Some(element) = *parent {
+                        visit(Visit::End(element))?;
+                    }
+
+                    stack.pop();
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Write the document as XML. Text is escaped where needed, while the
+    /// content of CDATA sections, comments and processing instructions is
+    /// written as is.
+    pub fn to_xml(&self, options: &WriteOptions) -> Result<String, Error> {
+        let mut writer = Formatter::new(vec![], options);
+
+        if let Some(declaration) = &self.declaration {
+            let declaration = serde_json::to_value(declaration).map_err(Error::JsonParseError)?;
+            to_xml::write_decl(&mut writer, &declaration)?;
+        }
+
+        if let Some(doctype) = &self.doctype {
+            writer.markup(b"<!DOCTYPE ", doctype.as_bytes(), b">")?;
+        }
+
+        self.walk(|visit| match visit {
+            Visit::Node(Node::Element(element)) => {
+                let mut start = BytesStart::borrowed_name(element.name.as_bytes());
+
+                for (key, value) in element.attributes.iter() {
+                    start.push_attribute(quick_xml::events::attributes::Attribute {
+                        key: key.as_bytes(),
+                        value: Cow::Owned(escape_attribute_value(value)),
+                    });
+                }
+
+                let style = ElementStyle {
+                    has_text: element
+                        .children
+                        .iter()
+                        .any(|child| matches!(child, Node::Text(_) | Node::CData(_))),
+                    ..ElementStyle::default()
+                };
+
+                match element.children.is_empty() {
+                    true => writer.empty(&start, &style),
+                    false => writer.start(&start, &style),
+                }
+            }
+            Visit::Node(Node::Text(text)) => writer.text(&BytesText::from_plain_str(text)),
+            Visit::Node(Node::CData(cdata)) => {
+                writer.markup(b"<![CDATA[", cdata.as_bytes(), b"]]>")
+            }
+            Visit::Node(Node::Comment(comment)) => {
+                writer.markup(b"<!--", comment.as_bytes(), b"-->")
+            }
+            Visit::Node(Node::PI(pi)) => writer.markup(b"<?", pi.as_bytes(), b"?>"),
+            Visit::End(element) => writer.end(&BytesEnd::borrowed(element.name.as_bytes())),
+        })?;
+
+        bytes_to_string(&writer.finish()?)
+    }
+
+    /// Read a JSON value following the jxon conventions, as `json_to_xml`
+    /// would write it.
+    pub fn from_value(value: &Value) -> Result<Document, Error> {
+        let options = ReadOptions {
+            limits: ParseLimits {
+                max_entity_expansion: None,
+                ..ParseLimits::default()
+            },
+            ..ReadOptions::default()
+        };
+        let mut builder = TreeBuilder::new(&options);
+
+        for event in to_events(value) {
+            builder.event(event?)?;
+        }
+
+        builder.finish()
+    }
+
+    /// The JSON value `xml_to_json` gives for the XML of the document. Like
+    /// it, this leaves out comments, processing instructions, CDATA sections
+    /// and text made only of whitespace, and joins the rest of the text of
+    /// an element.
+    pub fn to_value(&self) -> Result<Value, Error> {
+        let mut events = vec![];
+
+        if let Some(declaration) = &self.declaration {
+            match serde_json::to_value(declaration).map_err(Error::JsonParseError)? {
+                Value::Object(decl) => events.push(JxonEvent::Decl(decl)),
+                _ => return Err(Error::JsonParseInvalidDecl),
+            }
+        }
+
+        self.walk(|visit| {
+            match visit {
+                Visit::Node(Node::Element(element)) => {
+                    let attributes = element
+                        .attributes
+                        .iter()
+                        .map(|(key, value)| {
+                            (
+                                format!("{}{}", ATTRIBUTE_START_CHARACTER, key),
+                                Value::String(value.clone()),
+                            )
+                        })
+                        .collect::<Map<_, _>>();
+
+                    events.push(JxonEvent::StartElement {
+                        name: element.name.clone(),
+                        attributes,
+                    });
+
+                    if element.children.is_empty() {
+                        events.push(JxonEvent::EndElement {
+                            name: element.name.clone(),
+                        });
+                    }
+                }
+                Visit::Node(Node::Text(text)) => {
+                    let text = apply_whitespace(text.clone(), Whitespace::default());
+                    events.extend(text.map(JxonEvent::Text));
+                }
+                Visit::Node(_) => {}
+                Visit::End(element) => events.push(JxonEvent::EndElement {
+                    name: element.name.clone(),
+                }),
+            }
+
+            Ok(())
+        })?;
+
+        let events = events.into_iter().map(Ok);
+        Ok(Value::Object(build_document(
+            events,
+            &ReadOptions::default(),
+        )?))
+    }
+}
@@ -0,0 +1,137 @@
+use crate::{constants::*, to_xml::is_attribute_property_name};
+use serde_json::{Map, Value};
+use std::str::FromStr;
+
+/// Whether a key of an element object holds child elements rather than
+/// attributes, text, a declaration or style.
+fn is_element_key(key: &str) -> bool {
+    !is_attribute_property_name(key)
+        && !is_text_key(key)
+        && ![DECL_STRING, EMPTY_STYLE_STRING, QUOTES_STRING].contains(&key)
+}
+
+/// Whether a key of an element object holds its text.
+fn is_text_key(key: &str) -> bool {
+    key.len() == TEXT_CHARACTER.len_utf8() && key.starts_with(TEXT_CHARACTER)
+}
+
+/// A borrowed view of an element in a JSON value following the jxon
+/// conventions, such as one `xml_to_json` returns, for reading it without
+/// indexing into arrays. Whatever is missing or of the wrong type reads as
+/// `None` rather than panicking.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ElementRef<'a> {
+    name: Option<&'a str>,
+    object: &'a Map<String, Value>,
+}
+
+impl<'a> ElementRef<'a> {
+    /// View a document, whose root element is then its child, or an element
+    /// object whose name is not known, or `None` if `value` is not an
+    /// object.
+    pub fn new(value: &'a Value) -> Option<Self> {
+        value
+            .as_object()
+            .map(|object| ElementRef { name: None, object })
+    }
+
+    /// The name of the element, unless it was viewed directly with
+    /// [`ElementRef::new`].
+    pub fn name(&self) -> Option<&'a str> {
+        self.name
+    }
+
+    /// The object the element is held in.
+    pub fn as_object(&self) -> &'a Map<String, Value> {
+        self.object
+    }
+
+    /// The elements named `name` directly inside this one, in order.
+    pub fn children(&self, name: &str) -> impl Iterator<Item = ElementRef<'a>> + 'a {
+        let (name, values) = match self.object.get_key_value(name) {
+            Some((name, Value::Array(values))) if is_element_key(name) => {
+                (Some(name.as_str()), values.as_slice())
+            }
+            _ => (None, &[][..]),
+        };
+
+        values
+            .iter()
+            .filter_map(move |value| value.as_object().map(|object| ElementRef { name, object }))
+    }
+
+    /// The first element named `name` directly inside this one.
+    pub fn child(&self, name: &str) -> Option<ElementRef<'a>> {
+        self.children(name).next()
+    }
+
+    /// Every element directly inside this one, grouped by name in the order
+    /// the names first appear.
+    pub fn elements(&self) -> impl Iterator<Item = ElementRef<'a>> + 'a {
+        self.object
+            .iter()
+            .filter(|(key, _)| is_element_key(key))
+            .filter_map(|(key, value)| Some((key, value.as_array()?)))
+            .flat_map(|(name, values)| {
+                values.iter().filter_map(move |value| {
+                    value.as_object().map(|object| ElementRef {
+                        name: Some(name.as_str()),
+                        object,
+                    })
+                })
+            })
+    }
+
+    /// Every element inside this one at any depth, each before its own
+    /// children, keeping the elements left to visit on an explicit stack.
+    pub fn descendants(&self) -> impl Iterator<Item = ElementRef<'a>> + 'a {
+        let mut stack = vec![self.elements()];
+
+        std::iter::from_fn(move || {
+            while let Some(elements) = stack.last_mut() {
+                match elements.next() {
+                    Some(element) => {
+                        stack.push(element.elements());
+                        return Some(element);
+                    }
+                    None => {
+                        stack.pop();
+                    }
+                }
+            }
+
+            None
+        })
+    }
+
+    /// The value of the attribute named `name`.
+    pub fn attr(&self, name: &str) -> Option<&'a str> {
+        self.attrs()
+            .find(|(key, _)| *key == name)
+            .map(|(_, value)| value)
+    }
+
+    /// The name and value of each attribute, in order.
+    pub fn attrs(&self) -> impl Iterator<Item = (&'a str, &'a str)> + 'a {
+        self.object.iter().filter_map(|(key, value)| {
+            Some((
+                key.strip_prefix(ATTRIBUTE_START_CHARACTER)?,
+                value.as_str()?,
+            ))
+        })
+    }
+
+    /// The value of the attribute named `name` parsed as a `T`, or `None` if
+    /// the element has no such attribute.
+    pub fn parse_attr<T: FromStr>(&self, name: &str) -> Option<Result<T, T::Err>> {
+        self.attr(name).map(str::parse)
+    }
+
+    /// The text content of the element.
+    pub fn text(&self) -> Option<&'a str> {
+        let mut key = [0; 4];
+        self.object
+            .get(TEXT_CHARACTER.encode_utf8(&mut key) as &str)?
+            .as_str()
+    }
+}
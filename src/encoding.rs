@@ -0,0 +1,221 @@
+use crate::error::Error;
+use encoding_rs::{EncoderResult, Encoding, UTF_16BE, UTF_16LE, UTF_8, WINDOWS_1252};
+use std::borrow::Cow;
+
+/// Find the value of the `encoding` pseudo-attribute of an XML declaration
+/// at the start of an ASCII-compatible document.
+fn declared_encoding(bytes: &[u8]) -> Option<&[u8]> {
+    if !bytes.starts_with(b"<?xml") {
+        return None;
+    }
+
+    let decl = &bytes[..bytes.windows(2).position(|w| w == b"?>")?];
+    let start = decl.windows(8).position(|w| w == b"encoding")? + 8;
+    let rest = &decl[start..];
+    let rest = &rest[rest.iter().position(|&b| b == b'=')? + 1..];
+    let rest = &rest[rest.iter().position(|b| !b.is_ascii_whitespace())?..];
+    let quote = *rest.first()?;
+
+    if quote != b'"' && quote != b'\'' {
+        return None;
+    }
+
+    let rest = &rest[1..];
+    Some(&rest[..rest.iter().position(|&b| b == quote)?])
+}
+
+/// Work out the encoding of an XML document from its byte order mark or
+/// declaration, returning it with the length of the byte order mark.
+pub(crate) fn detect_encoding(bytes: &[u8]) -> Result<(&'static Encoding, usize), Error> {
+    if let Some((encoding, bom_length)) = Encoding::for_bom(bytes) {
+        return Ok((encoding, bom_length));
+    }
+
+    if bytes.starts_with(&[b'<', 0, b'?', 0]) {
+        return Ok((UTF_16LE, 0));
+    }
+
+    if bytes.starts_with(&[0, b'<', 0, b'?']) {
+        return Ok((UTF_16BE, 0));
+    }
+
+    match declared_encoding(bytes) {
+        None => Ok((UTF_8, 0)),
+        Some(label) => match Encoding::for_label_no_replacement(label) {
+            // A declaration readable as ASCII cannot be in UTF-16 without
+            // having been caught above, so it names the wrong encoding.
+            Some(encoding) if encoding == UTF_16LE || encoding == UTF_16BE => Ok((UTF_8, 0)),
+            Some(encoding) => Ok((encoding, 0)),
+            None => Err(Error::XmlUnsupportedEncoding(
+                String::from_utf8_lossy(label).into_owned(),
+            )),
+        },
+    }
+}
+
+/// Decode an XML document to UTF-8 following its byte order mark or
+/// declaration.
+pub(crate) fn decode(bytes: &[u8]) -> Result<(Cow<'_, str>, &'static Encoding), Error> {
+    let (encoding, bom_length) = detect_encoding(bytes)?;
+
+    if encoding == WINDOWS_1252 {
+        let range = declared_encoding(bytes)
+            .and_then(|label| single_byte_range(&String::from_utf8_lossy(label)));
+
+        if let Some((name, max)) = range {
+            return decode_single_byte(bytes, name, max).map(|string| (string, encoding));
+        }
+    }
+
+    encoding
+        .decode_without_bom_handling_and_without_replacement(&bytes[bom_length..])
+        .map(|string| (string, encoding))
+        .ok_or_else(|| Error::XmlMalformedEncoding(encoding.name()))
+}
+
+/// Tracks whether a position in written XML is inside markup, where a
+/// character cannot be replaced with a character reference.
+#[derive(Default)]
+struct MarkupScanner {
+    position: usize,
+    in_tag: bool,
+    quote: Option<char>,
+}
+
+impl MarkupScanner {
+    /// Whether the character at `index` is part of a name, scanning forward
+    /// from the last position checked.
+    fn in_name(&mut self, xml: &str, index: usize) -> bool {
+        for c in xml[self.position..index].chars() {
+            match (self.in_tag, self.quote, c) {
+                (false, _, '<') => self.in_tag = true,
+                (true, None, '>') => self.in_tag = false,
+                (true, None, '"') | (true, None, '\'') => self.quote = Some(c),
+                (true, Some(quote), c) if c == quote => self.quote = None,
+                _ => {}
+            }
+        }
+
+        self.position = index;
+        self.in_tag && self.quote.is_none()
+    }
+}
+
+fn encode_utf16(xml: &str, big_endian: bool) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(xml.len() * 2 + 2);
+
+    for unit in std::iter::once(0xfeff).chain(xml.encode_utf16()) {
+        match big_endian {
+            true => bytes.extend_from_slice(&unit.to_be_bytes()),
+            false => bytes.extend_from_slice(&unit.to_le_bytes()),
+        }
+    }
+
+    bytes
+}
+
+/// The name and highest character of the encoding named by `label` when it
+/// is US-ASCII or ISO-8859-1, each character being the byte of the same
+/// value. encoding_rs takes their labels to name windows-1252, which has
+/// other characters for some of the bytes.
+fn single_byte_range(label: &str) -> Option<(&'static str, char)> {
+    match label.trim().to_ascii_lowercase().as_str() {
+        "us-ascii" | "ascii" | "ansi_x3.4-1968" => Some(("US-ASCII", '\u{7f}')),
+        "iso-8859-1" | "iso8859-1" | "iso88591" | "iso_8859-1" | "iso_8859-1:1987"
+        | "iso-ir-100" | "latin1" | "l1" | "cp819" | "ibm819" | "csisolatin1" => {
+            Some(("ISO-8859-1", '\u{ff}'))
+        }
+        _ => None,
+    }
+}
+
+/// Decode XML in the encoding `name`, whose characters are those up to
+/// `max`, each read from a byte of the same value.
+fn decode_single_byte<'b>(
+    bytes: &'b [u8],
+    name: &'static str,
+    max: char,
+) -> Result<Cow<'b, str>, Error> {
+    if bytes.is_ascii() {
+        return std::str::from_utf8(bytes)
+            .map(Cow::Borrowed)
+            .map_err(Error::ParseStringError);
+    }
+
+    bytes
+        .iter()
+        .map(|&b| match char::from(b) {
+            c if c <= max => Ok(c),
+            _ => Err(Error::XmlMalformedEncoding(name)),
+        })
+        .collect::<Result<String, _>>()
+        .map(Cow::Owned)
+}
+
+/// Encode written XML in an encoding whose characters are those up to
+/// `max`, written as a byte of the same value.
+fn encode_single_byte(xml: &str, max: char) -> Result<Vec<u8>, Error> {
+    let mut scanner = MarkupScanner::default();
+    let mut bytes = Vec::with_capacity(xml.len());
+
+    for (i, c) in xml.char_indices() {
+        if c <= max {
+            bytes.push(c as u8);
+        } else if scanner.in_name(xml, i) {
+            return Err(Error::JsonParseUnencodableName(c));
+        } else {
+            bytes.extend_from_slice(format!("&#{};", c as u32).as_bytes());
+        }
+    }
+
+    Ok(bytes)
+}
+
+/// Encode written XML in the encoding named by `label`. Characters the
+/// encoding cannot represent are written as character references, which is
+/// an error inside element and attribute names.
+pub(crate) fn encode(xml: &str, label: &str) -> Result<Vec<u8>, Error> {
+    if let Some((_, max)) = single_byte_range(label) {
+        return encode_single_byte(xml, max);
+    }
+
+    let encoding = Encoding::for_label_no_replacement(label.as_bytes())
+        .ok_or_else(|| Error::JsonParseUnsupportedEncoding(label.to_owned()))?;
+
+    if encoding == UTF_8 {
+        return Ok(xml.as_bytes().to_vec());
+    } else if encoding == UTF_16LE {
+        return Ok(encode_utf16(xml, false));
+    } else if encoding == UTF_16BE {
+        return Ok(encode_utf16(xml, true));
+    } else if encoding.output_encoding() != encoding {
+        return Err(Error::JsonParseUnsupportedEncoding(label.to_owned()));
+    }
+
+    let mut encoder = encoding.new_encoder();
+    let mut scanner = MarkupScanner::default();
+    let mut bytes = Vec::with_capacity(xml.len());
+    let mut buffer = [0; 1024];
+    let mut position = 0;
+
+    loop {
+        let (result, read, written) =
+            encoder.encode_from_utf8_without_replacement(&xml[position..], &mut buffer, true);
+        bytes.extend_from_slice(&buffer[..written]);
+        position += read;
+
+        match result {
+            EncoderResult::InputEmpty => break,
+            EncoderResult::OutputFull => {}
+            EncoderResult::Unmappable(c) => {
+                if scanner.in_name(xml, position - c.len_utf8()) {
+                    return Err(Error::JsonParseUnencodableName(c));
+                }
+
+                bytes.extend_from_slice(format!("&#{};", c as u32).as_bytes());
+            }
+        }
+    }
+
+    Ok(bytes)
+}
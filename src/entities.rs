@@ -0,0 +1,299 @@
+use crate::{error::Error, html::html_entity, recover::Recovery};
+use std::collections::HashMap;
+
+fn predefined_entity(name: &str) -> Option<char> {
+    match name {
+        "lt" => Some('<'),
+        "gt" => Some('>'),
+        "amp" => Some('&'),
+        "apos" => Some('\''),
+        "quot" => Some('"'),
+        _ => None,
+    }
+}
+
+/// Resolve the part of a character reference after `&#`.
+fn character_reference(code: &str) -> Result<char, Error> {
+    let parsed = match code.strip_prefix('x') {
+        Some(hex) => u32::from_str_radix(hex, 16),
+        None => code.parse::<u32>(),
+    };
+
+    parsed
+        .ok()
+        .filter(|&code| code != 0)
+        .and_then(std::char::from_u32)
+        .ok_or_else(|| Error::XmlParseInvalidEntity(format!("#{}", code)))
+}
+
+/// Expand the character references of an entity value, leaving entity
+/// references to be expanded where the entity is used.
+fn expand_character_references(value: &str) -> Result<String, Error> {
+    let mut expanded = String::with_capacity(value.len());
+    let mut rest = value;
+
+    while let Some(i) = rest.find("&#") {
+        expanded.push_str(&rest[..i]);
+
+        let end = rest[i..]
+            .find(';')
+            .ok_or_else(|| Error::XmlParseInvalidEntity(rest[i + 1..].to_owned()))?;
+        expanded.push(character_reference(&rest[i + 2..i + end])?);
+        rest = &rest[i + end + 1..];
+    }
+
+    expanded.push_str(rest);
+    Ok(expanded)
+}
+
+/// Whether a character may appear between the `&` and `;` of a reference.
+fn is_reference_character(c: char) -> bool {
+    c.is_alphanumeric() || matches!(c, '#' | '_' | '-' | '.' | ':') || !c.is_ascii()
+}
+
+fn skip_whitespace(text: &str) -> &str {
+    text.trim_start_matches(|c: char| c.is_ascii_whitespace())
+}
+
+/// Parse the rest of an `<!ENTITY` declaration, returning the name and
+/// replacement text of an internal general entity along with the text after
+/// the declaration.
+fn parse_entity_declaration(declaration: &str) -> (Option<(&str, &str)>, &str) {
+    let rest = skip_whitespace(declaration);
+
+    // Parameter entities are only used within the DTD itself.
+    if !rest.starts_with('%') {
+        let name_end = rest
+            .find(|c: char| c.is_ascii_whitespace())
+            .unwrap_or(rest.len());
+        let (name, rest) = rest.split_at(name_end);
+        let rest = skip_whitespace(rest);
+
+        if let Some(quote) = rest.chars().next().filter(|&c| c == '"' || c == '\'') {
+            if let Some(end) = rest[1..].find(quote) {
+                let value = &rest[1..end + 1];
+                let rest = &rest[end + 2..];
+                let rest = &rest[rest.find('>').map_or(rest.len(), |i| i + 1)..];
+
+                return (Some((name, value)), rest);
+            }
+        }
+    }
+
+    // External entities are never fetched, so they are skipped along with
+    // anything malformed.
+    let mut quote = None;
+
+    for (i, c) in rest.char_indices() {
+        match (quote, c) {
+            (None, '"') | (None, '\'') => quote = Some(c),
+            (Some(q), c) if q == c => quote = None,
+            (None, '>') => return (None, &rest[i + 1..]),
+            _ => {}
+        }
+    }
+
+    (None, "")
+}
+
+/// What a character or entity reference stands for.
+enum Reference<'r> {
+    /// A character reference or predefined entity.
+    Character(char),
+    /// An entity supplied by the user, inserted as is.
+    Literal(&'r str),
+    /// An entity declared in the document, whose replacement text may hold
+    /// further references.
+    Declared(&'r str, &'r str),
+}
+
+/// Resolve the reference to `name`, given the declared entities being
+/// expanded around it.
+fn resolve<'r>(
+    name: &'r str,
+    custom: &'r HashMap<String, String>,
+    declared: &'r HashMap<String, String>,
+    html: bool,
+    expanding: &[(Option<&str>, &str)],
+) -> Result<Reference<'r>, Error> {
+    if let Some(code) = name.strip_prefix('#') {
+        character_reference(code).map(Reference::Character)
+    } else if let Some(c) = predefined_entity(name) {
+        Ok(Reference::Character(c))
+    } else if let Some(replacement) = declared.get(name) {
+        match expanding.iter().any(|(entity, _)| *entity == Some(name)) {
+            true => Err(Error::XmlParseRecursiveEntity(name.to_owned())),
+            false => Ok(Reference::Declared(name, replacement)),
+        }
+    } else if let Some(replacement) = custom.get(name) {
+        Ok(Reference::Literal(replacement))
+    } else if let Some(replacement) = html_entity(name).filter(|_| html) {
+        Ok(Reference::Literal(replacement))
+    } else {
+        Err(Error::XmlParseUnknownEntity(name.to_owned()))
+    }
+}
+
+/// Expands entity references, from the predefined entities, the entities
+/// supplied by the user and those declared in the document's internal DTD
+/// subset, keeping count of the text they expand to.
+pub(crate) struct Entities<'o> {
+    custom: &'o HashMap<String, String>,
+    declared: HashMap<String, String>,
+    /// Also expand the HTML named character references, and read any `&`
+    /// that does not start a known reference as literal text, as HTML does.
+    html: bool,
+    max_expansion: Option<usize>,
+    expanded: usize,
+}
+
+impl<'o> Entities<'o> {
+    pub(crate) fn new(
+        custom: &'o HashMap<String, String>,
+        html: bool,
+        max_expansion: Option<usize>,
+    ) -> Self {
+        Entities {
+            custom,
+            declared: HashMap::new(),
+            html,
+            max_expansion,
+            expanded: 0,
+        }
+    }
+
+    /// Record the internal general entities declared in the content of a
+    /// `<!DOCTYPE ...>` declaration. The first declaration of a name wins.
+    pub(crate) fn declare_internal_subset(&mut self, doctype: &str) -> Result<(), Error> {
+        let mut rest = match (doctype.find('['), doctype.rfind(']')) {
+            (Some(start), Some(end)) if start < end => &doctype[start + 1..end],
+            _ => return Ok(()),
+        };
+
+        while let Some(i) = rest.find('<') {
+            rest = &rest[i..];
+
+            if let Some(comment) = rest.strip_prefix("<!--") {
+                rest = comment.find("-->").map_or("", |end| &comment[end + 3..]);
+            } else if let Some(declaration) = rest.strip_prefix("<!ENTITY") {
+                let (entity, after) = parse_entity_declaration(declaration);
+
+                if let Some((name, value)) = entity {
+                    if !self.declared.contains_key(name) {
+                        self.declared
+                            .insert(name.to_owned(), expand_character_references(value)?);
+                    }
+                }
+
+                rest = after;
+            } else {
+                rest = &rest[1..];
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Replace the character and entity references in raw text starting at
+    /// `position` in the input.
+    ///
+    /// Declared entities are expanded recursively with an explicit stack,
+    /// refusing self-referencing ones, while user-supplied entities are
+    /// inserted as literal text. A reference that cannot be resolved is kept
+    /// as literal text when reading HTML or when `recovery` allows it. Fails
+    /// once the total expanded text exceeds the limit.
+    pub(crate) fn unescape(
+        &mut self,
+        raw: &str,
+        position: usize,
+        recovery: &mut Recovery,
+    ) -> Result<String, Error> {
+        if !raw.contains('&') {
+            return Ok(raw.to_owned());
+        }
+
+        let Entities {
+            custom,
+            declared,
+            html,
+            max_expansion,
+            expanded,
+        } = self;
+        let mut unescaped = String::with_capacity(raw.len());
+        let mut stack: Vec<(Option<&str>, &str)> = vec![(None, raw)];
+        // The offset in `raw` of the reference being expanded, where any
+        // problem within its replacement text is reported.
+        let mut offset = 0;
+
+        loop {
+            let depth = stack.len();
+            let text = match stack.last_mut() {
+                Some((_, text)) => text,
+                None => break,
+            };
+            let current = *text;
+            let i = match current.find('&') {
+                Some(i) => i,
+                None => {
+                    unescaped.push_str(current);
+                    stack.pop();
+                    continue;
+                }
+            };
+
+            unescaped.push_str(&current[..i]);
+
+            if depth == 1 {
+                offset = raw.len() - current.len() + i;
+            }
+
+            let reference = &current[i + 1..];
+            let end = reference
+                .find(|c: char| !is_reference_character(c))
+                .unwrap_or(reference.len());
+            let resolved = match reference[end..].starts_with(';') {
+                false => Err(Error::XmlParseInvalidEntity(reference[..end].to_owned())),
+                true => {
+                    *text = &reference[end + 1..];
+                    resolve(&reference[..end], custom, declared, *html, &stack)
+                }
+            };
+
+            let replacement = match resolved {
+                Ok(Reference::Character(c)) => {
+                    unescaped.push(c);
+                    continue;
+                }
+                Ok(Reference::Literal(replacement)) => {
+                    unescaped.push_str(replacement);
+                    replacement
+                }
+                Ok(Reference::Declared(name, replacement)) => {
+                    stack.push((Some(name), replacement));
+                    replacement
+                }
+                Err(error) => {
+                    if !*html {
+                        recovery.recover(position + offset, error)?;
+                    }
+
+                    unescaped.push('&');
+
+                    if let Some((_, text)) = stack.last_mut() {
+                        *text = reference;
+                    }
+
+                    continue;
+                }
+            };
+
+            *expanded += replacement.len();
+
+            if max_expansion.is_some_and(|max| *expanded > max) {
+                return Err(Error::XmlParseEntityLimitExceeded);
+            }
+        }
+
+        Ok(unescaped)
+    }
+}
@@ -1,12 +1,27 @@
 use quick_xml::Error as QuickXmlError;
 use serde_json::Error as SerdeJsonError;
-use std::{fmt, str::Utf8Error};
+use std::{fmt, io, str::Utf8Error};
 
 #[derive(Debug)]
 pub enum Error {
     ParseStringError(Utf8Error),
+    IoError(io::Error),
     XmlQuickXmlError(QuickXmlError),
     XmlParseUnexpectedEof,
+    XmlParseDepthLimitExceeded,
+    XmlParseSizeLimitExceeded,
+    XmlParseAttributeLimitExceeded,
+    XmlParseTextLimitExceeded,
+    XmlParseElementLimitExceeded,
+    XmlParseEntityLimitExceeded,
+    XmlParseInvalidEntity(String),
+    XmlParseUnknownEntity(String),
+    XmlParseRecursiveEntity(String),
+    XmlParseUnclosedElement(String),
+    XmlParseUnexpectedEndTag(String),
+    XmlUnsupportedEncoding(String),
+    XmlMalformedEncoding(&'static str),
+    XmlInvalidPath(String),
     JsonParseError(SerdeJsonError),
     JsonParseInvalidAttributeName,
     JsonParseInvalidAttributeValue,
@@ -18,14 +33,60 @@ pub enum Error {
     JsonParseUnexpectedNumber,
     JsonParseDeclMissingVersion,
     JsonParseInvalidDecl,
+    JsonParseInvalidStyle,
+    JsonParseAttributeAfterContent,
+    JsonParseTextAfterIndentedContent,
+    JsonParseUnsupportedEncoding(String),
+    JsonParseUnencodableName(char),
 }
 
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             Error::ParseStringError(e) => write!(f, "Failed to parse utf8 string: {}", e),
+            Error::IoError(e) => write!(f, "io error: {}", e),
             Error::XmlQuickXmlError(e) => write!(f, "quick-xml error: {}", e),
             Error::XmlParseUnexpectedEof => write!(f, "xml parse error: Unexpected end of file"),
+            Error::XmlParseDepthLimitExceeded => {
+                write!(f, "xml parse error: maximum element depth exceeded")
+            }
+            Error::XmlParseSizeLimitExceeded => {
+                write!(f, "xml parse error: maximum document size exceeded")
+            }
+            Error::XmlParseAttributeLimitExceeded => {
+                write!(f, "xml parse error: maximum number of attributes exceeded")
+            }
+            Error::XmlParseTextLimitExceeded => {
+                write!(f, "xml parse error: maximum text length exceeded")
+            }
+            Error::XmlParseElementLimitExceeded => {
+                write!(f, "xml parse error: maximum number of elements exceeded")
+            }
+            Error::XmlParseEntityLimitExceeded => {
+                write!(f, "xml parse error: maximum entity expansion exceeded")
+            }
+            Error::XmlParseInvalidEntity(e) => {
+                write!(f, "xml parse error: invalid entity reference &{}", e)
+            }
+            Error::XmlParseUnknownEntity(e) => {
+                write!(f, "xml parse error: unknown entity &{};", e)
+            }
+            Error::XmlParseRecursiveEntity(e) => {
+                write!(f, "xml parse error: entity &{}; references itself", e)
+            }
+            Error::XmlParseUnclosedElement(e) => {
+                write!(f, "xml parse error: element <{}> is not closed", e)
+            }
+            Error::XmlParseUnexpectedEndTag(e) => {
+                write!(f, "xml parse error: unexpected end tag </{}>", e)
+            }
+            Error::XmlUnsupportedEncoding(e) => {
+                write!(f, "xml parse error: unsupported encoding {}", e)
+            }
+            Error::XmlMalformedEncoding(e) => {
+                write!(f, "xml parse error: malformed {} input", e)
+            }
+            Error::XmlInvalidPath(e) => write!(f, "invalid element path {:?}", e),
             Error::JsonParseError(e) => write!(f, "json parse error:  {}", e),
             Error::JsonParseInvalidAttributeName => {
                 write!(f, "json parse error: invalid attribute name")
@@ -43,6 +104,23 @@ impl fmt::Display for Error {
                 write!(f, "json parse error: missing xml version")
             }
             Error::JsonParseInvalidDecl => write!(f, "json parse error: invalid xml declaration"),
+            Error::JsonParseInvalidStyle => write!(f, "json parse error: invalid element style"),
+            Error::JsonParseAttributeAfterContent => write!(
+                f,
+                "json parse error: attribute or style after the content of an element"
+            ),
+            Error::JsonParseTextAfterIndentedContent => write!(
+                f,
+                "json parse error: text after indented children of an element"
+            ),
+            Error::JsonParseUnsupportedEncoding(e) => {
+                write!(f, "json parse error: unsupported encoding {}", e)
+            }
+            Error::JsonParseUnencodableName(c) => write!(
+                f,
+                "json parse error: {:?} in a name cannot be written in the declared encoding",
+                c
+            ),
         }
     }
 }
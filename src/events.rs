@@ -0,0 +1,253 @@
+use crate::{
+    constants::*,
+    error::Error,
+    recover::Recovery,
+    to_json::{append_content, Closed, Node, OpenElement, Parser, Sink},
+    *,
+};
+use quick_xml::Reader;
+use serde_json::{Map, Value};
+use std::{collections::VecDeque, io::BufRead, mem, sync::OnceLock};
+
+/// A structural event read from XML, with the jxon conventions applied: the
+/// attributes are keyed by `$` and their name, and text is what the `"_"`
+/// key would hold, after the whitespace policy.
+#[derive(Debug, Clone, PartialEq)]
+pub enum JxonEvent {
+    /// An XML declaration, holding its `version` and, if given, its
+    /// `encoding` and `standalone`.
+    Decl(Map<String, Value>),
+    /// The start of an element. When preserving style, the attributes also
+    /// hold `"#quotes"`, and `"#empty"` for an empty element tag.
+    StartElement {
+        name: String,
+        attributes: Map<String, Value>,
+    },
+    /// Text inside an element or at the top level of the document.
+    Text(String),
+    /// The end of the element started last, which an empty element tag also
+    /// has.
+    EndElement { name: String },
+}
+
+/// Queues the events for what a `Parser` reads, pausing it after each.
+#[derive(Default)]
+struct Queue {
+    events: VecDeque<JxonEvent>,
+    /// How many of the elements open on the parser's stack, from the
+    /// outermost, had their start queued. An element only kept on the way to
+    /// those a projection includes is started once something inside it is.
+    started: usize,
+}
+
+impl Queue {
+    /// Queue the start of every open element not started yet, so that
+    /// content can be queued inside the innermost one.
+    fn open(&mut self, stack: &mut [OpenElement]) {
+        for element in stack.iter_mut().skip(self.started) {
+            self.events.push_back(JxonEvent::StartElement {
+                name: element.name.clone(),
+                attributes: mem::take(&mut element.attributes),
+            });
+        }
+
+        self.started = stack.len();
+    }
+
+    /// Queue an event inside the innermost open element.
+    fn push(&mut self, stack: &mut [OpenElement], event: JxonEvent) -> Result<Option<()>, Error> {
+        self.open(stack);
+        self.events.push_back(event);
+
+        Ok(None)
+    }
+}
+
+impl Sink for Queue {
+    type Node = ();
+
+    fn is_paused(&self) -> bool {
+        !self.events.is_empty()
+    }
+
+    fn start(&mut self, stack: &mut [OpenElement]) -> Result<(), Error> {
+        if stack
+            .last()
+            .is_some_and(|element| element.selection.included)
+        {
+            self.open(stack);
+        }
+
+        Ok(())
+    }
+
+    fn element(
+        &mut self,
+        stack: &mut [OpenElement],
+        name: String,
+        element: Map<String, Value>,
+    ) -> Result<Option<()>, Error> {
+        self.push(
+            stack,
+            JxonEvent::StartElement {
+                name: name.clone(),
+                attributes: element,
+            },
+        )?;
+        self.push(stack, JxonEvent::EndElement { name })
+    }
+
+    fn text(&mut self, stack: &mut [OpenElement], text: String) -> Result<Option<()>, Error> {
+        self.push(stack, JxonEvent::Text(text))
+    }
+
+    fn decl(
+        &mut self,
+        stack: &mut [OpenElement],
+        decl: Map<String, Value>,
+    ) -> Result<Option<()>, Error> {
+        self.push(stack, JxonEvent::Decl(decl))
+    }
+
+    /// Elements are never returned whole, even at the top level, so that
+    /// the parser carries on until it is paused or the input ends.
+    fn end(&mut self, stack: &mut Vec<OpenElement>, _: &ReadOptions) -> Result<Closed<()>, Error> {
+        if let Some(element) = stack.pop() {
+            if stack.len() < self.started {
+                self.started = stack.len();
+                self.events
+                    .push_back(JxonEvent::EndElement { name: element.name });
+            }
+        }
+
+        Ok(Closed::Nested)
+    }
+}
+
+/// An iterator over the structural events of a document, created by
+/// [`events`] or [`events_with_options`].
+pub struct Events<'o, B: BufRead> {
+    parser: Parser<'o, B>,
+    queue: Queue,
+    /// The elements open around the next event.
+    stack: Vec<OpenElement>,
+    finished: bool,
+}
+
+impl<'o, B: BufRead> Events<'o, B> {
+    pub(crate) fn new(parser: Parser<'o, B>) -> Self {
+        Events {
+            parser,
+            queue: Queue::default(),
+            stack: vec![],
+            finished: false,
+        }
+    }
+
+    /// The parser the events are read with, such as to get the diagnostics
+    /// it recovered from.
+    pub(crate) fn into_parser(self) -> Parser<'o, B> {
+        self.parser
+    }
+}
+
+impl<B: BufRead> Iterator for Events<'_, B> {
+    type Item = Result<JxonEvent, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(event) = self.queue.events.pop_front() {
+                return Some(Ok(event));
+            }
+
+            if self.finished {
+                return None;
+            }
+
+            // The parser only stops with nothing queued at the end of the
+            // input.
+            match self.parser.read_node(&mut self.queue, &mut self.stack, 0) {
+                Ok(_) => self.finished = self.queue.events.is_empty(),
+                Err(e) => {
+                    self.finished = true;
+                    return Some(Err(e));
+                }
+            }
+        }
+    }
+}
+
+/// Build a map of the top level nodes of a document from its events, in
+/// the shape `xml_to_json` gives it.
+pub(crate) fn build_document<I>(
+    events: I,
+    options: &ReadOptions,
+) -> Result<Map<String, Value>, Error>
+where
+    I: Iterator<Item = Result<JxonEvent, Error>>,
+{
+    let mut document = Map::new();
+    // The children and attributes of each open element.
+    let mut stack: Vec<(Map<String, Value>, Map<String, Value>)> = vec![];
+
+    for event in events {
+        let node = match event? {
+            JxonEvent::Decl(decl) => Node::Decl(decl),
+            JxonEvent::Text(text) => Node::Text(text),
+            JxonEvent::StartElement { attributes, .. } => {
+                stack.push((Map::new(), attributes));
+                continue;
+            }
+            JxonEvent::EndElement { name } => {
+                let (mut children, mut element) = stack
+                    .pop()
+                    .ok_or_else(|| Error::XmlParseUnexpectedEndTag(name.clone()))?;
+                let empty = children.is_empty();
+
+                append_content(&mut element, &mut children);
+
+                // An empty element tag already says so in its attributes.
+                if options.preserve_style && empty {
+                    element
+                        .entry(EMPTY_STYLE_STRING)
+                        .or_insert_with(|| Value::String(EXPANDED_STRING.to_owned()));
+                }
+
+                Node::Element(name, element)
+            }
+        };
+
+        match stack.last_mut() {
+            Some((children, _)) => node.insert_into(children)?,
+            None => node.insert_into(&mut document)?,
+        }
+    }
+
+    Ok(document)
+}
+
+/// Read the structural events of a document one at a time. See
+/// [`events_with_options`].
+pub fn events<B: BufRead>(reader: B) -> Events<'static, B> {
+    static DEFAULT_OPTIONS: OnceLock<ReadOptions> = OnceLock::new();
+
+    events_with_options(reader, DEFAULT_OPTIONS.get_or_init(ReadOptions::default))
+}
+
+/// Read the structural events of a document one at a time using the given
+/// options, for building something other than a JSON value without keeping
+/// the document in memory. `xml_to_json` builds its value from these.
+///
+/// The options apply as they do to `xml_to_json_with_options`: comments,
+/// processing instructions, text dropped by the whitespace policy and
+/// elements left out by the projection have no events, and an element only
+/// kept on the way to those the projection includes is started, without its
+/// attributes, once one of them turns up inside it. The iterator ends after
+/// the first error.
+pub fn events_with_options<'o, B: BufRead>(reader: B, options: &'o ReadOptions) -> Events<'o, B> {
+    Events::new(Parser::new(
+        Reader::from_reader(reader),
+        options,
+        Recovery::strict(),
+    ))
+}
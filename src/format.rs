@@ -0,0 +1,210 @@
+use crate::{error::Error, html::is_void_element, options::*};
+use quick_xml::events::{BytesDecl, BytesEnd, BytesStart, BytesText};
+use std::io::Write;
+
+struct OpenElement {
+    has_text: bool,
+    has_children: bool,
+}
+
+/// How a single element is written, on top of the `WriteOptions`.
+#[derive(Default)]
+pub(crate) struct ElementStyle<'b> {
+    /// The element's content includes text, so nothing inside it is indented.
+    pub(crate) has_text: bool,
+    /// Overrides `WriteOptions::self_close_empty` for this element.
+    pub(crate) self_close: Option<bool>,
+    /// The quote character of each attribute in order. Attributes beyond
+    /// its length use `WriteOptions::quote`.
+    pub(crate) quotes: &'b [u8],
+}
+
+/// Writes XML markup to `W`, laid out according to a set of `WriteOptions`.
+pub(crate) struct Formatter<'a, W: Write> {
+    writer: W,
+    options: &'a WriteOptions,
+    stack: Vec<OpenElement>,
+    text_elements: usize,
+    started: bool,
+}
+
+impl<'a, W: Write> Formatter<'a, W> {
+    pub(crate) fn new(writer: W, options: &'a WriteOptions) -> Self {
+        Formatter {
+            writer,
+            options,
+            stack: vec![],
+            text_elements: 0,
+            started: false,
+        }
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> Result<(), Error> {
+        self.started = true;
+        self.writer.write_all(bytes).map_err(Error::IoError)
+    }
+
+    fn write_indent(&mut self, depth: usize) -> Result<(), Error> {
+        if let Some(indent) = self.options.indent {
+            for _ in 0..depth * indent.size {
+                self.write(&[indent.character])?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Start a new line at the current depth, unless the output is empty,
+    /// indentation is off or an open element has text content.
+    fn line_break(&mut self) -> Result<(), Error> {
+        if self.options.indent.is_some() && self.started && self.text_elements == 0 {
+            self.write(self.options.newline.as_bytes())?;
+            self.write_indent(self.stack.len())?;
+        }
+
+        Ok(())
+    }
+
+    fn write_start_tag(&mut self, start: &BytesStart, style: &ElementStyle) -> Result<(), Error> {
+        let attributes = start
+            .attributes()
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(Error::XmlQuickXmlError)?;
+        let per_line = self
+            .options
+            .attributes_per_line
+            .is_some_and(|n| attributes.len() > n);
+
+        self.write(b"<")?;
+        self.write(start.name())?;
+
+        for (i, attribute) in attributes.into_iter().enumerate() {
+            let quote = style
+                .quotes
+                .get(i)
+                .copied()
+                .unwrap_or_else(|| self.options.quote.as_byte());
+
+            if per_line {
+                self.write(self.options.newline.as_bytes())?;
+                self.write_indent(self.stack.len() + 1)?;
+            } else {
+                self.write(b" ")?;
+            }
+
+            self.write(attribute.key)?;
+            self.write(&[b'=', quote])?;
+
+            for &b in attribute.value.iter() {
+                match b {
+                    b'"' if b == quote => self.write(b"&quot;")?,
+                    b'\'' if b == quote => self.write(b"&apos;")?,
+                    _ => self.write(&[b])?,
+                }
+            }
+
+            self.write(&[quote])?;
+        }
+
+        Ok(())
+    }
+
+    fn mark_child(&mut self) {
+        if let Some(parent) = self.stack.last_mut() {
+            parent.has_children = true;
+        }
+    }
+
+    pub(crate) fn decl(&mut self, decl: &BytesDecl) -> Result<(), Error> {
+        self.line_break()?;
+        self.write(b"<?")?;
+        self.write(decl)?;
+        self.write(b"?>")
+    }
+
+    /// Write markup holding `content` as is, such as a comment or a CDATA
+    /// section, on its own line like an element.
+    pub(crate) fn markup(
+        &mut self,
+        open: &[u8],
+        content: &[u8],
+        close: &[u8],
+    ) -> Result<(), Error> {
+        self.mark_child();
+        self.line_break()?;
+        self.write(open)?;
+        self.write(content)?;
+        self.write(close)
+    }
+
+    pub(crate) fn start(&mut self, start: &BytesStart, style: &ElementStyle) -> Result<(), Error> {
+        self.mark_child();
+        self.line_break()?;
+        self.write_start_tag(start, style)?;
+        self.write(b">")?;
+
+        if style.has_text {
+            self.text_elements += 1;
+        }
+
+        self.stack.push(OpenElement {
+            has_text: style.has_text,
+            has_children: false,
+        });
+
+        Ok(())
+    }
+
+    pub(crate) fn empty(&mut self, start: &BytesStart, style: &ElementStyle) -> Result<(), Error> {
+        self.mark_child();
+        self.line_break()?;
+        self.write_start_tag(start, style)?;
+
+        if self.options.html && is_void_element(start.name()) {
+            self.write(b">")
+        } else if self.options.html || !style.self_close.unwrap_or(self.options.self_close_empty) {
+            self.write(b"></")?;
+            self.write(start.name())?;
+            self.write(b">")
+        } else if self.options.space_before_self_close {
+            self.write(b" />")
+        } else {
+            self.write(b"/>")
+        }
+    }
+
+    /// Write text, which cannot follow children that were laid out on lines
+    /// of their own, as that would change the content of the element.
+    pub(crate) fn text(&mut self, text: &BytesText) -> Result<(), Error> {
+        if let Some(element) = self.stack.last() {
+            if self.options.indent.is_some() && self.text_elements == 0 && element.has_children {
+                return Err(Error::JsonParseTextAfterIndentedContent);
+            }
+        }
+
+        self.write(text.escaped())
+    }
+
+    pub(crate) fn end(&mut self, end: &BytesEnd) -> Result<(), Error> {
+        if let Some(element) = self.stack.pop() {
+            if element.has_text {
+                self.text_elements -= 1;
+            } else if element.has_children {
+                self.line_break()?;
+            }
+        }
+
+        self.write(b"</")?;
+        self.write(end.name())?;
+        self.write(b">")
+    }
+
+    /// Finish the document and return the underlying writer.
+    pub(crate) fn finish(mut self) -> Result<W, Error> {
+        if self.options.trailing_newline {
+            self.write(self.options.newline.as_bytes())?;
+        }
+
+        Ok(self.writer)
+    }
+}
@@ -0,0 +1,22 @@
+use crate::html_entities::HTML_ENTITIES;
+
+/// The elements that never have content in HTML, and so have no end tag.
+const VOID_ELEMENTS: &[&str] = &[
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "source", "track",
+    "wbr",
+];
+
+/// Whether an element is an HTML void element, ignoring case.
+pub(crate) fn is_void_element(name: &[u8]) -> bool {
+    VOID_ELEMENTS
+        .iter()
+        .any(|void| void.as_bytes().eq_ignore_ascii_case(name))
+}
+
+/// The replacement text of an HTML named character reference.
+pub(crate) fn html_entity(name: &str) -> Option<&'static str> {
+    HTML_ENTITIES
+        .binary_search_by(|(entity, _)| entity.as_bytes().cmp(name.as_bytes()))
+        .ok()
+        .map(|i| HTML_ENTITIES[i].1)
+}
@@ -0,0 +1,294 @@
+use crate::{
+    constants::*,
+    error::Error,
+    events::{build_document, Events},
+    recover::Recovery,
+    to_json::{Closed, OpenElement, Parser, Sink},
+    *,
+};
+use quick_xml::Reader;
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::io::{self, BufRead, Write};
+
+fn write_json<W: Write, T: Serialize + ?Sized>(writer: &mut W, value: &T) -> io::Result<()> {
+    serde_json::to_writer(writer, value).map_err(io::Error::from)
+}
+
+/// A JSON object being written.
+#[derive(Default)]
+struct Object {
+    /// Whether an entry was written, so the next one needs a comma.
+    entries: bool,
+    /// The name of the array of elements written last, left open so that a
+    /// sibling directly following with the same name joins it.
+    run: Option<String>,
+}
+
+impl Object {
+    /// Close the array of elements written last, if any.
+    fn end_run<W: Write>(&mut self, writer: &mut W) -> io::Result<()> {
+        match self.run.take() {
+            Some(_) => writer.write_all(b"]"),
+            None => Ok(()),
+        }
+    }
+
+    /// Write the key of a new entry.
+    fn key<W: Write>(&mut self, writer: &mut W, key: &str) -> io::Result<()> {
+        self.end_run(writer)?;
+
+        if self.entries {
+            writer.write_all(b",")?;
+        }
+
+        self.entries = true;
+        write_json(writer, key)?;
+        writer.write_all(b":")
+    }
+
+    /// Write an entry with a value.
+    fn entry<W: Write, T: Serialize + ?Sized>(
+        &mut self,
+        writer: &mut W,
+        key: &str,
+        value: &T,
+    ) -> io::Result<()> {
+        self.key(writer, key)?;
+        write_json(writer, value)
+    }
+
+    /// Start the object of an element named `name`, adding it to the array of
+    /// its previous sibling if that has the same name.
+    fn element<W: Write>(&mut self, writer: &mut W, name: &str) -> io::Result<()> {
+        if self.run.as_deref() == Some(name) {
+            return writer.write_all(b",{");
+        }
+
+        self.key(writer, name)?;
+        self.run = Some(name.to_owned());
+        writer.write_all(b"[{")
+    }
+}
+
+/// The output state of an open element.
+#[derive(Default)]
+struct Frame {
+    object: Object,
+    /// Whether the start of its object was written. An element only kept on
+    /// the way to those a projection includes is written once something
+    /// inside it is.
+    written: bool,
+    /// Whether any child or text was written inside it.
+    content: bool,
+}
+
+/// Writes nodes as JSON text in document order as they are read.
+struct JsonWriter<W: Write> {
+    writer: W,
+    document: Object,
+    /// One frame for each element open on the parser's stack.
+    frames: Vec<Frame>,
+}
+
+impl<W: Write> JsonWriter<W> {
+    /// Write the start of every open element not written yet, so that
+    /// content can be written inside the innermost one.
+    fn open(&mut self, stack: &[OpenElement]) -> io::Result<()> {
+        let first = match self.frames.iter().position(|frame| !frame.written) {
+            Some(first) => first,
+            None => return Ok(()),
+        };
+
+        for (i, element) in stack.iter().enumerate().skip(first) {
+            let (outer, inner) = self.frames.split_at_mut(i);
+            let parent = match outer.last_mut() {
+                Some(parent) => {
+                    parent.content = true;
+                    &mut parent.object
+                }
+                None => &mut self.document,
+            };
+            let frame = &mut inner[0];
+
+            parent.element(&mut self.writer, &element.name)?;
+
+            for (key, value) in element.attributes.iter() {
+                frame.object.entry(&mut self.writer, key, value)?;
+            }
+
+            frame.written = true;
+        }
+
+        Ok(())
+    }
+
+    /// Write an entry in the innermost open element, or else the document.
+    fn entry<T: Serialize + ?Sized>(
+        &mut self,
+        stack: &[OpenElement],
+        key: &str,
+        value: &T,
+    ) -> Result<Option<()>, Error> {
+        self.open(stack).map_err(Error::IoError)?;
+
+        let object = match self.frames.last_mut() {
+            Some(frame) => {
+                frame.content = true;
+                &mut frame.object
+            }
+            None => &mut self.document,
+        };
+
+        object
+            .entry(&mut self.writer, key, value)
+            .map_err(Error::IoError)?;
+
+        Ok(stack.is_empty().then_some(()))
+    }
+}
+
+impl<W: Write> Sink for JsonWriter<W> {
+    type Node = ();
+
+    fn start(&mut self, stack: &mut [OpenElement]) -> Result<(), Error> {
+        self.frames.push(Frame::default());
+
+        match stack
+            .last()
+            .is_some_and(|element| element.selection.included)
+        {
+            true => self.open(stack).map_err(Error::IoError),
+            false => Ok(()),
+        }
+    }
+
+    fn element(
+        &mut self,
+        stack: &mut [OpenElement],
+        name: String,
+        element: Map<String, Value>,
+    ) -> Result<Option<()>, Error> {
+        self.open(stack).map_err(Error::IoError)?;
+
+        let parent = match self.frames.last_mut() {
+            Some(frame) => {
+                frame.content = true;
+                &mut frame.object
+            }
+            None => &mut self.document,
+        };
+        let mut object = Object::default();
+        let writer = &mut self.writer;
+
+        parent.element(writer, &name).map_err(Error::IoError)?;
+
+        for (key, value) in element.iter() {
+            object.entry(writer, key, value).map_err(Error::IoError)?;
+        }
+
+        writer.write_all(b"}").map_err(Error::IoError)?;
+        Ok(stack.is_empty().then_some(()))
+    }
+
+    fn text(&mut self, stack: &mut [OpenElement], text: String) -> Result<Option<()>, Error> {
+        self.entry(stack, &TEXT_CHARACTER.to_string(), &text)
+    }
+
+    fn decl(
+        &mut self,
+        stack: &mut [OpenElement],
+        decl: Map<String, Value>,
+    ) -> Result<Option<()>, Error> {
+        self.entry(stack, DECL_STRING, &decl)
+    }
+
+    fn end(
+        &mut self,
+        stack: &mut Vec<OpenElement>,
+        options: &ReadOptions,
+    ) -> Result<Closed<()>, Error> {
+        stack.pop();
+
+        let mut frame = match self.frames.pop() {
+            Some(frame) => frame,
+            None => return Ok(Closed::Outermost(None)),
+        };
+
+        if frame.written {
+            let writer = &mut self.writer;
+            frame.object.end_run(writer).map_err(Error::IoError)?;
+
+            if options.preserve_style && !frame.content {
+                frame
+                    .object
+                    .entry(writer, EMPTY_STYLE_STRING, EXPANDED_STRING)
+                    .map_err(Error::IoError)?;
+            }
+
+            writer.write_all(b"}").map_err(Error::IoError)?;
+        }
+
+        match stack.is_empty() {
+            true => Ok(Closed::Outermost(frame.written.then_some(()))),
+            false => Ok(Closed::Nested),
+        }
+    }
+}
+
+/// Convert XML read from `reader` to JSON text written to `writer`, writing
+/// each node as soon as it is read. This is
+/// [`xml_to_json_writer_with_options`] with `ReadOptions::document_order`
+/// set, which describes the text it writes.
+pub fn xml_to_json_writer<B: BufRead, W: Write>(reader: B, writer: W) -> Result<(), Error> {
+    let options = ReadOptions {
+        document_order: true,
+        ..ReadOptions::default()
+    };
+
+    xml_to_json_writer_with_options(reader, writer, &options)
+}
+
+/// Convert XML read from `reader` to JSON text written to `writer` using the
+/// given options.
+///
+/// With `ReadOptions::document_order`, each node is written as soon as it is
+/// read, keeping just the elements open around it in memory. Siblings with
+/// the same name that follow each other are then written as one array, as
+/// usual. A sibling that repeats the name of an earlier one it does not
+/// directly follow starts a new array under the same key, as does any text
+/// after the first in an element for the `"_"` key, so the object has
+/// duplicate keys. The text is still valid JSON and keeps every node in
+/// order, and `json_to_xml_writer` reads it back as such, while
+/// `json_to_xml` fails on the duplicate keys rather than losing nodes.
+///
+/// Otherwise the text is that of the value `xml_to_json` returns, so the
+/// whole document is read into memory before anything is written.
+pub fn xml_to_json_writer_with_options<B: BufRead, W: Write>(
+    reader: B,
+    mut writer: W,
+    options: &ReadOptions,
+) -> Result<(), Error> {
+    let mut parser = Parser::new(Reader::from_reader(reader), options, Recovery::strict());
+
+    if !options.document_order {
+        let document = Value::Object(build_document(Events::new(parser), options)?);
+        write_json(&mut writer, &document).map_err(Error::IoError)?;
+        return writer.flush().map_err(Error::IoError);
+    }
+
+    let mut sink = JsonWriter {
+        writer,
+        document: Object::default(),
+        frames: vec![],
+    };
+
+    sink.writer.write_all(b"{").map_err(Error::IoError)?;
+
+    while parser.read_node(&mut sink, &mut vec![], 0)?.is_some() {}
+
+    let writer = &mut sink.writer;
+    sink.document.end_run(writer).map_err(Error::IoError)?;
+    writer.write_all(b"}").map_err(Error::IoError)?;
+    writer.flush().map_err(Error::IoError)
+}
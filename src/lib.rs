@@ -1,31 +1,78 @@
+#[cfg(feature = "tokio")]
+mod async_io;
+mod c14n;
 mod constants;
+mod dom;
+mod element_ref;
+mod encoding;
+mod entities;
 mod error;
+mod events;
+mod format;
+mod html;
+mod html_entities;
+mod json_writer;
+mod macros;
+mod options;
+mod projection;
+mod push;
+mod records;
+mod recover;
 mod to_json;
 mod to_xml;
+mod xml_writer;
 
+#[cfg(feature = "tokio")]
+pub use async_io::{
+    json_to_xml_writer_async, records_async, records_with_options_async,
+    xml_documents_to_json_async, xml_to_json_writer_async, xml_to_json_writer_with_options_async,
+    AsyncValues,
+};
+pub use c14n::{canonicalize_json, canonicalize_xml};
+pub use dom::{Document, Element, Node};
+pub use element_ref::ElementRef;
 pub use error::Error;
+pub use events::{events, events_with_options, Events, JxonEvent};
+pub use json_writer::{xml_to_json_writer, xml_to_json_writer_with_options};
+#[doc(hidden)]
+pub use macros::{
+    __xml_build, __xml_element_name, __xml_end, __xml_same_name, __xml_start, __xml_string,
+    __xml_text,
+};
+pub use options::{
+    CanonicalOptions, Indent, Newline, ParseLimits, QuoteStyle, ReadOptions, Whitespace,
+    WriteOptions,
+};
+pub use push::JxonParser;
+pub use records::{records, records_with_options, Records};
+pub use recover::Diagnostic;
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use std::str::from_utf8;
-pub use to_json::xml_to_json;
-pub use to_xml::json_to_xml;
+pub use to_json::{
+    parse_element, parse_element_with_options, xml_bytes_to_json, xml_bytes_to_json_with_options,
+    xml_documents_to_json, xml_fragments_to_json, xml_to_json, xml_to_json_lenient,
+    xml_to_json_with_options, Documents,
+};
+pub use to_xml::{json_to_xml, json_to_xml_bytes, to_events};
+pub use xml_writer::json_to_xml_writer;
 
 fn bytes_to_string(bytes: &[u8]) -> Result<String, Error> {
     from_utf8(bytes)
         .map(|s| s.to_owned())
-        .map_err(|e| Error::ParseStringError(e))
+        .map_err(Error::ParseStringError)
 }
 
 /// Serialize a JXON compatible struct into an XML string.
-pub fn serialize<T: Serialize>(t: T, indent: Option<(u8, usize)>) -> Result<String, Error> {
+pub fn serialize<T: Serialize>(t: T, options: &WriteOptions) -> Result<String, Error> {
     json_to_xml(
-        &serde_json::to_string(&t).map_err(|e| Error::JsonParseError(e))?,
-        indent,
+        &serde_json::to_string(&t).map_err(Error::JsonParseError)?,
+        options,
     )
 }
 
 /// Deserialize an XML string into a JXON compatible struct.
 pub fn deserialize<T: DeserializeOwned>(xml: &str) -> Result<T, Error> {
-    serde_json::from_value(xml_to_json(xml)?).map_err(|e| Error::JsonParseError(e))
+    serde_json::from_value(xml_to_json(xml)?).map_err(Error::JsonParseError)
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
@@ -0,0 +1,216 @@
+use crate::{
+    constants::*,
+    events::{build_document, JxonEvent},
+    *,
+};
+use serde_json::{Map, Value};
+
+/// Build a JSON value following the jxon conventions from XML-like markup,
+/// ready for `json_to_xml`.
+///
+/// Element and attribute names are identifiers, which may be joined by `:`
+/// or `-` as in `soap:Envelope` or `data-id`, or else string literals.
+/// Attribute values and text are literals or Rust expressions in braces,
+/// turned into text with `ToString` and escaped when the value is written;
+/// text split up by child elements is joined. Each end tag must name the
+/// element it closes, and element names must not be keys the jxon
+/// conventions use for anything else, which is checked when compiling.
+#[macro_export]
+macro_rules! xml {
+    ($($markup:tt)+) => {{
+        let mut events = ::std::vec::Vec::new();
+        $crate::__xml_content!(events [] $($markup)+);
+        $crate::__xml_build(events)
+    }};
+}
+
+/// Push the events for the content of the elements whose names are open on
+/// the stack in brackets, innermost first.
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __xml_content {
+    ($events:ident []) => {};
+    ($events:ident [$($open:tt)*] < / $($rest:tt)*) => {
+        $crate::__xml_name!(__xml_end_tag [$events [$($open)*]] [] $($rest)*);
+    };
+    ($events:ident [$($open:tt)*] < $($rest:tt)*) => {
+        $crate::__xml_name!(__xml_start_tag [$events [$($open)*]] [] $($rest)*);
+    };
+    ($events:ident [$($open:tt)*] {$text:expr} $($rest:tt)*) => {
+        $events.push($crate::__xml_text(&$text));
+        $crate::__xml_content!($events [$($open)*] $($rest)*);
+    };
+    ($events:ident [$($open:tt)*] $text:literal $($rest:tt)*) => {
+        $crate::__xml_content!($events [$($open)*] {$text} $($rest)*);
+    };
+}
+
+/// Read the name at the start of the tokens, collecting the pieces of one
+/// made of identifiers in brackets, and pass it to `callback` after its
+/// arguments as a string expression in parentheses.
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __xml_name {
+    ($callback:ident [$($arguments:tt)*] [] $name:literal $($rest:tt)*) => {
+        $crate::$callback!($($arguments)* ($name) $($rest)*);
+    };
+    ($callback:ident [$($arguments:tt)*] [] $first:ident $($rest:tt)*) => {
+        $crate::__xml_name!(
+            $callback [$($arguments)*] [::std::stringify!($first)] $($rest)*
+        );
+    };
+    ($callback:ident [$($arguments:tt)*] [$($pieces:tt)+] : $next:ident $($rest:tt)*) => {
+        $crate::__xml_name!(
+            $callback [$($arguments)*] [$($pieces)+, ":", ::std::stringify!($next)] $($rest)*
+        );
+    };
+    ($callback:ident [$($arguments:tt)*] [$($pieces:tt)+] - $next:ident $($rest:tt)*) => {
+        $crate::__xml_name!(
+            $callback [$($arguments)*] [$($pieces)+, "-", ::std::stringify!($next)] $($rest)*
+        );
+    };
+    ($callback:ident [$($arguments:tt)*] [$($pieces:tt)+] $($rest:tt)*) => {
+        $crate::$callback!($($arguments)* (::std::concat!($($pieces)+)) $($rest)*);
+    };
+}
+
+/// Check the name of a start tag, then read its attributes.
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __xml_start_tag {
+    ($events:ident [$($open:tt)*] ($name:expr) $($rest:tt)*) => {
+        const _: () = ::std::assert!(
+            $crate::__xml_element_name($name),
+            ::std::concat!("<", $name, "> is not an element name jxon can hold")
+        );
+        $crate::__xml_attributes!($events [$($open)*] ($name) [] $($rest)*);
+    };
+}
+
+/// Collect the attributes of a start tag named `name` until it ends.
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __xml_attributes {
+    ($events:ident [$($open:tt)*] ($name:expr) [$($attributes:tt)*] / > $($rest:tt)*) => {
+        $events.push($crate::__xml_start($name, &[$($attributes)*]));
+        $events.push($crate::__xml_end($name));
+        $crate::__xml_content!($events [$($open)*] $($rest)*);
+    };
+    ($events:ident [$($open:tt)*] ($name:expr) [$($attributes:tt)*] > $($rest:tt)*) => {
+        $events.push($crate::__xml_start($name, &[$($attributes)*]));
+        $crate::__xml_content!($events [($name) $($open)*] $($rest)*);
+    };
+    (
+        $events:ident [$($open:tt)*] ($name:expr) [$($attributes:tt)*]
+        ($key:expr) = {$value:expr} $($rest:tt)*
+    ) => {
+        $crate::__xml_attributes!(
+            $events [$($open)*] ($name)
+            [$($attributes)* ($key, $crate::__xml_string(&$value)),]
+            $($rest)*
+        );
+    };
+    (
+        $events:ident [$($open:tt)*] ($name:expr) [$($attributes:tt)*]
+        ($key:expr) = $value:literal $($rest:tt)*
+    ) => {
+        $crate::__xml_attributes!($events [$($open)*] ($name) [$($attributes)*] ($key) = {$value} $($rest)*);
+    };
+    ($events:ident [$($open:tt)*] ($name:expr) [$($attributes:tt)*] $($rest:tt)+) => {
+        $crate::__xml_name!(
+            __xml_attributes [$events [$($open)*] ($name) [$($attributes)*]] [] $($rest)+
+        );
+    };
+}
+
+/// Check that an end tag closes the innermost open element.
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __xml_end_tag {
+    ($events:ident [($open:expr) $($outer:tt)*] ($name:expr) > $($rest:tt)*) => {
+        const _: () = ::std::assert!(
+            $crate::__xml_same_name($open, $name),
+            ::std::concat!("</", $name, "> does not close <", $open, ">")
+        );
+        $events.push($crate::__xml_end($name));
+        $crate::__xml_content!($events [$($outer)*] $($rest)*);
+    };
+}
+
+#[doc(hidden)]
+pub const fn __xml_same_name(open: &str, end: &str) -> bool {
+    let (open, end) = (open.as_bytes(), end.as_bytes());
+
+    if open.len() != end.len() {
+        return false;
+    }
+
+    let mut i = 0;
+
+    while i < open.len() {
+        if open[i] != end[i] {
+            return false;
+        }
+
+        i += 1;
+    }
+
+    true
+}
+
+/// Whether `name` can be the name of an element, rather than being a key
+/// for text, attributes, a declaration or style.
+#[doc(hidden)]
+pub const fn __xml_element_name(name: &str) -> bool {
+    match name.as_bytes() {
+        [] => false,
+        [b] if *b == TEXT_CHARACTER as u8 => false,
+        [b, ..] if *b == ATTRIBUTE_START_CHARACTER as u8 => false,
+        _ => {
+            !__xml_same_name(name, DECL_STRING)
+                && !__xml_same_name(name, EMPTY_STYLE_STRING)
+                && !__xml_same_name(name, QUOTES_STRING)
+        }
+    }
+}
+
+#[doc(hidden)]
+pub fn __xml_string<T: ToString + ?Sized>(value: &T) -> String {
+    value.to_string()
+}
+
+#[doc(hidden)]
+pub fn __xml_start(name: &str, attributes: &[(&str, String)]) -> JxonEvent {
+    JxonEvent::StartElement {
+        name: name.to_owned(),
+        attributes: attributes
+            .iter()
+            .map(|(key, value)| {
+                (
+                    format!("{}{}", ATTRIBUTE_START_CHARACTER, key),
+                    Value::String(value.clone()),
+                )
+            })
+            .collect::<Map<_, _>>(),
+    }
+}
+
+#[doc(hidden)]
+pub fn __xml_end(name: &str) -> JxonEvent {
+    JxonEvent::EndElement {
+        name: name.to_owned(),
+    }
+}
+
+#[doc(hidden)]
+pub fn __xml_text<T: ToString + ?Sized>(text: &T) -> JxonEvent {
+    JxonEvent::Text(text.to_string())
+}
+
+#[doc(hidden)]
+pub fn __xml_build(events: Vec<JxonEvent>) -> Value {
+    // The macro only accepts element names that cannot clash with the other
+    // keys, and text is joined, so the events always build a value.
+    let document = build_document(events.into_iter().map(Ok), &ReadOptions::default());
+    Value::Object(document.expect("xml! markup builds a value"))
+}
@@ -0,0 +1,202 @@
+use std::collections::HashMap;
+
+/// How text content is treated when reading XML.
+///
+/// Inside an element with `xml:space="preserve"`, text is always kept as is;
+/// `xml:space="default"` switches back to the chosen policy.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum Whitespace {
+    /// Keep all text, including text made only of whitespace.
+    Preserve,
+    /// Drop text made only of whitespace and keep the rest untouched.
+    #[default]
+    DropWhitespaceOnly,
+    /// Remove leading and trailing whitespace, dropping text that ends up
+    /// empty.
+    Trim,
+    /// Trim like `Trim` and replace each inner run of whitespace with a
+    /// single space.
+    Collapse,
+}
+
+/// Limits on the size and shape of a document, for reading untrusted input.
+/// Each limit is off when `None`. By default only entity expansion is
+/// limited.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseLimits {
+    /// The deepest an element may be nested, the root element being at
+    /// depth 1.
+    pub max_depth: Option<usize>,
+    /// The number of bytes of input that may be read.
+    pub max_bytes: Option<usize>,
+    /// The number of attributes an element may have.
+    pub max_attributes: Option<usize>,
+    /// The number of characters a single text node may have.
+    pub max_text_length: Option<usize>,
+    /// The number of elements a document may have.
+    pub max_elements: Option<usize>,
+    /// The total number of bytes of replacement text that entity references
+    /// may expand to, which stops exponential expansion such as the "billion
+    /// laughs" attack. Defaults to 1 MiB.
+    pub max_entity_expansion: Option<usize>,
+}
+
+impl Default for ParseLimits {
+    fn default() -> Self {
+        ParseLimits {
+            max_depth: None,
+            max_bytes: None,
+            max_attributes: None,
+            max_text_length: None,
+            max_elements: None,
+            max_entity_expansion: Some(1024 * 1024),
+        }
+    }
+}
+
+/// Options controlling how XML is read into a JSON value.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ReadOptions {
+    /// Apply XML attribute-value normalization to attribute values, replacing
+    /// every literal tab, carriage return and newline with a space (a `\r\n`
+    /// pair becomes a single space). Whitespace written as a character
+    /// reference such as `&#10;` is kept, exactly as a validating parser would
+    /// report it for a CDATA attribute.
+    pub normalize_attribute_values: bool,
+    /// Record how each element was written so that `json_to_xml` can
+    /// reproduce it: `"#empty"` holds `"self-closing"` or `"expanded"` for
+    /// elements without content, and `"#quotes"` holds the quote character
+    /// of each attribute in order.
+    pub preserve_style: bool,
+    /// How whitespace in text content is handled.
+    pub whitespace: Whitespace,
+    /// Limits that cause reading to fail with an error when exceeded.
+    pub limits: ParseLimits,
+    /// Entities that may be referenced in addition to the predefined ones,
+    /// by name without the `&` and `;`, mapped to the text they stand for.
+    /// Entities declared in the document's internal DTD subset take
+    /// precedence.
+    pub entities: HashMap<String, String>,
+    /// Read the input as HTML: void elements such as `<br>` need no end
+    /// tag, attributes may be unquoted or have no value (which reads as an
+    /// empty string), element and attribute names are lowercased so tags
+    /// match regardless of case, the HTML named character references are
+    /// available and a `&` that does not start a reference is literal text.
+    pub html: bool,
+    /// Keep only the elements matching one of these patterns, with all of
+    /// their content, and the elements on the way to those that turn up,
+    /// without their attributes or text. A pattern is an absolute element
+    /// path such as `/feed/entry/title`, where `*` stands for any one
+    /// element and `**` for any number of them. Everything is kept when
+    /// empty.
+    pub include: Vec<String>,
+    /// Leave out the elements matching one of these patterns, written like
+    /// those of `include`, along with all of their content. Takes
+    /// precedence over `include`.
+    pub exclude: Vec<String>,
+    /// Have `xml_to_json_writer_with_options` write each node as soon as it
+    /// is read, in document order, rather than the value `xml_to_json`
+    /// returns, which takes the whole document to build. It describes how
+    /// elements with the same name are written then. `xml_to_json_writer`
+    /// always sets it.
+    pub document_order: bool,
+}
+
+/// Indentation used when pretty printing.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Indent {
+    /// The byte repeated for each level, usually `b' '` or `b'\t'`.
+    pub character: u8,
+    /// How many times `character` is repeated per nesting level.
+    pub size: usize,
+}
+
+/// The line ending written between lines of output.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Newline {
+    /// `\n`
+    Lf,
+    /// `\r\n`
+    CrLf,
+}
+
+impl Newline {
+    pub(crate) fn as_bytes(self) -> &'static [u8] {
+        match self {
+            Newline::Lf => b"\n",
+            Newline::CrLf => b"\r\n",
+        }
+    }
+}
+
+/// The character used to quote attribute values.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum QuoteStyle {
+    /// `name="value"`
+    Double,
+    /// `name='value'`
+    Single,
+}
+
+impl QuoteStyle {
+    pub(crate) fn as_byte(self) -> u8 {
+        match self {
+            QuoteStyle::Double => b'"',
+            QuoteStyle::Single => b'\'',
+        }
+    }
+}
+
+/// Options controlling how a JSON value is written as XML.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WriteOptions {
+    /// Put each element on its own line, indented by its depth. Elements
+    /// with text content are always written inline so no whitespace is
+    /// added to their content.
+    pub indent: Option<Indent>,
+    /// The line ending used for every line break the writer adds.
+    pub newline: Newline,
+    /// Write elements without content as `<a/>` rather than `<a></a>`.
+    pub self_close_empty: bool,
+    /// Write self-closing elements as `<a />` rather than `<a/>`.
+    pub space_before_self_close: bool,
+    /// The character used to quote attribute values.
+    pub quote: QuoteStyle,
+    /// Write each attribute on its own line when an element has more
+    /// attributes than this.
+    pub attributes_per_line: Option<usize>,
+    /// End the document with a line break.
+    pub trailing_newline: bool,
+    /// Write HTML rather than XML: void elements such as `<br>` have no end
+    /// tag or `/>`, and other elements are never self-closing.
+    pub html: bool,
+}
+
+impl Default for WriteOptions {
+    fn default() -> Self {
+        WriteOptions {
+            indent: None,
+            newline: Newline::Lf,
+            self_close_empty: true,
+            space_before_self_close: false,
+            quote: QuoteStyle::Double,
+            attributes_per_line: None,
+            trailing_newline: false,
+            html: false,
+        }
+    }
+}
+
+/// Options controlling canonical XML output.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CanonicalOptions {
+    /// Use Exclusive XML Canonicalization, which only declares namespaces
+    /// where they are visibly used, instead of Canonical XML 1.0.
+    pub exclusive: bool,
+    /// Keep comments, as the "WithComments" variants of both methods do.
+    pub with_comments: bool,
+    /// The InclusiveNamespaces PrefixList of Exclusive XML
+    /// Canonicalization: prefixes that are declared following the Canonical
+    /// XML 1.0 rules. `#default` stands for the default namespace.
+    pub inclusive_prefixes: Vec<String>,
+}
@@ -0,0 +1,189 @@
+use crate::error::Error;
+
+/// One step of a path pattern.
+enum Step {
+    /// An element with this name.
+    Name(String),
+    /// `*`: any one element.
+    Any,
+    /// `**`: any number of elements, including none.
+    AnyDepth,
+}
+
+/// An absolute element path in which `*` matches any one element and `**`
+/// matches any number of elements, such as `/feed/*/title` or `/**/link`.
+struct Pattern {
+    steps: Vec<Step>,
+}
+
+impl Pattern {
+    fn parse(pattern: &str) -> Result<Self, Error> {
+        let steps = pattern
+            .strip_prefix('/')
+            .map(|steps| steps.split('/').collect::<Vec<_>>())
+            .filter(|steps| steps.iter().all(|step| !step.is_empty()))
+            .ok_or_else(|| Error::XmlInvalidPath(pattern.to_owned()))?;
+
+        Ok(Pattern {
+            steps: steps
+                .into_iter()
+                .map(|step| match step {
+                    "*" => Step::Any,
+                    "**" => Step::AnyDepth,
+                    name => Step::Name(name.to_owned()),
+                })
+                .collect(),
+        })
+    }
+
+    /// Add the steps that `**` lets a pattern skip to a set of matched
+    /// step counts.
+    fn close(&self, mut matched: Vec<usize>) -> Vec<usize> {
+        let mut i = 0;
+
+        while i < matched.len() {
+            let step = matched[i];
+
+            if let Some(Step::AnyDepth) = self.steps.get(step) {
+                if !matched.contains(&(step + 1)) {
+                    matched.push(step + 1);
+                }
+            }
+
+            i += 1;
+        }
+
+        matched
+    }
+
+    /// The number of steps matched before any element.
+    fn start(&self) -> Vec<usize> {
+        self.close(vec![0])
+    }
+
+    /// The numbers of steps matched by a path once it continues with
+    /// `name`, given those matched by the path to its parent.
+    fn advance(&self, matched: &[usize], name: &str) -> Vec<usize> {
+        let mut advanced = vec![];
+
+        for &step in matched {
+            let next = match self.steps.get(step) {
+                Some(Step::Name(step_name)) if step_name == name => step + 1,
+                Some(Step::Any) => step + 1,
+                Some(Step::AnyDepth) => step,
+                _ => continue,
+            };
+
+            if !advanced.contains(&next) {
+                advanced.push(next);
+            }
+        }
+
+        self.close(advanced)
+    }
+
+    fn is_matched(&self, matched: &[usize]) -> bool {
+        matched.contains(&self.steps.len())
+    }
+}
+
+/// How an element that is kept relates to a projection.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Selection {
+    /// The element matches an include pattern or is inside one that does,
+    /// so its attributes and text are kept. Otherwise it is only kept to
+    /// hold the elements inside it that may match one.
+    pub(crate) included: bool,
+    /// The steps matched by each include pattern, left empty once included.
+    include: Vec<Vec<usize>>,
+    /// The steps matched by each exclude pattern.
+    exclude: Vec<Vec<usize>>,
+}
+
+/// The elements to keep when reading a document, chosen by path patterns.
+#[derive(Default)]
+pub(crate) struct Projection {
+    include: Vec<Pattern>,
+    exclude: Vec<Pattern>,
+}
+
+impl Projection {
+    pub(crate) fn new(include: &[String], exclude: &[String]) -> Result<Self, Error> {
+        Ok(Projection {
+            include: include
+                .iter()
+                .map(|pattern| Pattern::parse(pattern))
+                .collect::<Result<_, _>>()?,
+            exclude: exclude
+                .iter()
+                .map(|pattern| Pattern::parse(pattern))
+                .collect::<Result<_, _>>()?,
+        })
+    }
+
+    /// The selection of the document itself, outside of any element.
+    pub(crate) fn root(&self) -> Selection {
+        Selection {
+            included: self.include.is_empty(),
+            include: self.include.iter().map(Pattern::start).collect(),
+            exclude: self.exclude.iter().map(Pattern::start).collect(),
+        }
+    }
+
+    /// The selection of an element named `name` inside an element selected
+    /// by `parent`, or `None` if the element and its content are skipped.
+    pub(crate) fn select(&self, parent: &Selection, name: &str) -> Option<Selection> {
+        let exclude = self
+            .exclude
+            .iter()
+            .zip(parent.exclude.iter())
+            .map(|(pattern, matched)| pattern.advance(matched, name))
+            .collect::<Vec<_>>();
+
+        if self
+            .exclude
+            .iter()
+            .zip(exclude.iter())
+            .any(|(pattern, matched)| pattern.is_matched(matched))
+        {
+            return None;
+        }
+
+        if parent.included {
+            return Some(Selection {
+                included: true,
+                include: vec![],
+                exclude,
+            });
+        }
+
+        let include = self
+            .include
+            .iter()
+            .zip(parent.include.iter())
+            .map(|(pattern, matched)| pattern.advance(matched, name))
+            .collect::<Vec<_>>();
+
+        if self
+            .include
+            .iter()
+            .zip(include.iter())
+            .any(|(pattern, matched)| pattern.is_matched(matched))
+        {
+            return Some(Selection {
+                included: true,
+                include: vec![],
+                exclude,
+            });
+        }
+
+        match include.iter().all(|matched| matched.is_empty()) {
+            true => None,
+            false => Some(Selection {
+                included: false,
+                include,
+                exclude,
+            }),
+        }
+    }
+}
@@ -0,0 +1,357 @@
+use crate::{
+    error::Error,
+    html::is_void_element,
+    recover::Recovery,
+    to_json::{read_name, OpenElement, Parser},
+    *,
+};
+use quick_xml::{
+    events::{BytesStart, Event},
+    Reader,
+};
+use serde_json::{Map, Value};
+use std::{
+    io::{BufRead, Cursor},
+    sync::OnceLock,
+};
+
+/// The length of the markup at the start of `bytes`, which starts with a
+/// `<`, or `None` if the rest of it has not arrived yet.
+fn markup_length(bytes: &[u8]) -> Option<usize> {
+    let find = |from: usize, end: &[u8]| {
+        bytes
+            .get(from..)?
+            .windows(end.len())
+            .position(|window| window == end)
+            .map(|i| from + i + end.len())
+    };
+
+    if bytes.starts_with(b"<!--") {
+        return find(4, b"-->");
+    } else if bytes.starts_with(b"<![CDATA[") {
+        return find(9, b"]]>");
+    } else if bytes.starts_with(b"<?") {
+        return find(2, b"?>");
+    } else if b"<!--".starts_with(bytes) || b"<![CDATA[".starts_with(bytes) {
+        // Too little has arrived to tell what kind of markup it is.
+        return None;
+    }
+
+    // A tag ends at the first `>` outside quotes, and a DOCTYPE declaration
+    // after its internal subset. Comments and processing instructions in
+    // the subset are skipped, since a quote or bracket in them means
+    // nothing.
+    let declaration = bytes.starts_with(b"<!");
+    let mut quote = None;
+    let mut brackets = 0usize;
+    let mut i = 0;
+
+    while let Some(&b) = bytes.get(i) {
+        match (quote, b) {
+            (None, b'<') if brackets > 0 && bytes[i..].starts_with(b"<!--") => {
+                i = find(i + 4, b"-->")?;
+                continue;
+            }
+            (None, b'<') if brackets > 0 && bytes[i..].starts_with(b"<?") => {
+                i = find(i + 2, b"?>")?;
+                continue;
+            }
+            (None, b'"') | (None, b'\'') => quote = Some(b),
+            (Some(q), b) if q == b => quote = None,
+            (None, b'[') if declaration => brackets += 1,
+            (None, b']') if declaration => brackets = brackets.saturating_sub(1),
+            (None, b'>') if brackets == 0 => return Some(i + 1),
+            _ => {}
+        }
+
+        i += 1;
+    }
+
+    None
+}
+
+/// The element name at the start of the inside of a tag.
+fn tag_name(tag: &[u8]) -> &[u8] {
+    let end = tag
+        .iter()
+        .position(|&b| b.is_ascii_whitespace() || b == b'/' || b == b'>')
+        .unwrap_or(tag.len());
+
+    &tag[..end]
+}
+
+/// Read the start tag at the start of the input of `reader`, telling
+/// whether it is an empty element tag.
+fn read_start<B: BufRead>(
+    reader: &mut Reader<B>,
+    buf: &mut Vec<u8>,
+) -> Result<Option<(BytesStart<'static>, bool)>, Error> {
+    loop {
+        buf.clear();
+
+        match reader.read_event(buf) {
+            Ok(Event::Start(e)) => return Ok(Some((e.into_owned(), false))),
+            Ok(Event::Empty(e)) => return Ok(Some((e.into_owned(), true))),
+            Ok(Event::Eof) => return Ok(None),
+            Ok(_) => {}
+            Err(e) => return Err(Error::XmlQuickXmlError(e)),
+        }
+    }
+}
+
+/// A push parser for XML that arrives in chunks, such as from a network
+/// connection, returning the elements at a chosen depth as soon as their end
+/// tag arrives. Created by [`JxonParser::new`] or
+/// [`JxonParser::with_options`].
+///
+/// Only the input from the start of the element being read is kept, along
+/// with the elements enclosing it, so a stream that never ends, such as an
+/// XMPP session, can be read as a sequence of stanzas. Once the end tag of
+/// an element arrives, its input is copied out of the buffer to be parsed,
+/// so it is held twice in memory while it is turned into a value. The input
+/// must be UTF-8.
+pub struct JxonParser<'o> {
+    parser: Parser<'o, Cursor<Vec<u8>>>,
+    /// The depth of the elements returned, the root element being at
+    /// depth 1.
+    depth: usize,
+    /// An error to return before reading anything.
+    error: Option<Error>,
+    /// The input not read yet, from the start of the record being read if
+    /// there is one.
+    buffer: Vec<u8>,
+    /// How much of `buffer` has been scanned for markup.
+    scanned: usize,
+    /// The elements enclosing the next record, with all of their
+    /// attributes.
+    ancestors: Vec<OpenElement>,
+    /// How many elements are open inside the record being read or an
+    /// element left out by the projection.
+    nested: usize,
+    /// Where the record being read starts in `buffer`.
+    record: Option<usize>,
+}
+
+impl JxonParser<'static> {
+    /// Read the elements at `depth`, such as 1 for a sequence of top level
+    /// elements or 2 for the stanzas inside the root element of an XMPP
+    /// stream. See [`JxonParser::with_options`].
+    pub fn new(depth: usize) -> Self {
+        static DEFAULT_OPTIONS: OnceLock<ReadOptions> = OnceLock::new();
+
+        JxonParser::with_options(depth, DEFAULT_OPTIONS.get_or_init(ReadOptions::default))
+    }
+}
+
+impl<'o> JxonParser<'o> {
+    /// Read the elements at `depth` using the given options, returning each
+    /// as a JSON value holding just that element, in the same shape as
+    /// `records_with_options` gives it. A depth of 0 is taken as 1.
+    ///
+    /// The `ParseLimits` apply to each element returned on its own, with
+    /// `max_bytes` also limiting how much input is kept waiting for the end
+    /// of one.
+    pub fn with_options(depth: usize, options: &'o ReadOptions) -> Self {
+        let mut parser = Parser::new(
+            Reader::from_reader(Cursor::new(vec![])),
+            options,
+            Recovery::strict(),
+        );
+
+        JxonParser {
+            error: parser.error.take(),
+            parser,
+            depth: depth.max(1),
+            buffer: vec![],
+            scanned: 0,
+            ancestors: vec![],
+            nested: 0,
+            record: None,
+        }
+    }
+
+    /// The name and attributes of each element enclosing the next element
+    /// returned, outermost first, as given by [`Records::ancestors`].
+    pub fn ancestors(&self) -> impl Iterator<Item = (&str, &Map<String, Value>)> {
+        self.ancestors
+            .iter()
+            .map(|ancestor| (ancestor.name.as_str(), &ancestor.attributes))
+    }
+
+    /// Add the next chunk of input, which may end anywhere, even inside a
+    /// tag or character, and return the elements it completes. The parser
+    /// should not be used after it returns an error.
+    pub fn feed(&mut self, bytes: &[u8]) -> Result<Vec<Value>, Error> {
+        if let Some(error) = self.error.take() {
+            return Err(error);
+        }
+
+        let mut records = vec![];
+        self.buffer.extend_from_slice(bytes);
+
+        loop {
+            let start = match self.buffer[self.scanned..].iter().position(|&b| b == b'<') {
+                Some(i) => self.scanned + i,
+                None => {
+                    self.scanned = self.buffer.len();
+                    break;
+                }
+            };
+            let end = match markup_length(&self.buffer[start..]) {
+                Some(length) => start + length,
+                None => {
+                    self.scanned = start;
+                    break;
+                }
+            };
+
+            self.scanned = end;
+
+            if let Some(record) = self.read_markup(start, end)? {
+                records.push(record);
+            }
+        }
+
+        let read = self.record.unwrap_or(self.scanned);
+        self.buffer.drain(..read);
+        self.scanned -= read;
+        self.record = self.record.map(|_| 0);
+
+        let limits = &self.parser.builder.options.limits;
+
+        if limits.max_bytes.is_some_and(|max| self.buffer.len() > max) {
+            return Err(Error::XmlParseSizeLimitExceeded);
+        }
+
+        Ok(records)
+    }
+
+    /// End the input, failing if it stops inside an element or markup.
+    pub fn finish(self) -> Result<(), Error> {
+        if let Some(error) = self.error {
+            return Err(error);
+        }
+
+        match self.nested > 0 || !self.ancestors.is_empty() || self.scanned < self.buffer.len() {
+            true => Err(Error::XmlParseUnexpectedEof),
+            false => Ok(()),
+        }
+    }
+
+    /// Follow the complete markup found between `start` and `end` in the
+    /// buffer, returning the record it completes if any.
+    fn read_markup(&mut self, start: usize, end: usize) -> Result<Option<Value>, Error> {
+        let options = self.parser.builder.options;
+        let markup = &self.buffer[start..end];
+
+        if markup.starts_with(b"</") {
+            let name = tag_name(&markup[2..]);
+
+            // The end tag of an HTML void element is ignored like its
+            // missing one.
+            if options.html && is_void_element(name) {
+                return Ok(None);
+            }
+
+            if self.nested > 0 {
+                self.nested -= 1;
+
+                return match (self.nested, self.record) {
+                    (0, Some(record)) => {
+                        self.record = None;
+                        self.read_record(record, end)
+                    }
+                    _ => Ok(None),
+                };
+            }
+
+            let name = read_name(name, options)?;
+
+            return match self.ancestors.pop() {
+                Some(ancestor) if ancestor.name == name => Ok(None),
+                _ => Err(Error::XmlParseUnexpectedEndTag(name)),
+            };
+        }
+
+        if markup.starts_with(b"<!") || markup.starts_with(b"<?") {
+            if self.nested == 0 && markup.starts_with(b"<!DOCTYPE") {
+                let doctype = bytes_to_string(&markup[9..markup.len() - 1])?;
+                self.parser
+                    .builder
+                    .entities
+                    .declare_internal_subset(&doctype)?;
+            }
+
+            return Ok(None);
+        }
+
+        let name = tag_name(&markup[1..]);
+        let empty = markup.ends_with(b"/>") || (options.html && is_void_element(name));
+
+        if self.nested > 0 {
+            if !empty {
+                self.nested += 1;
+            }
+
+            return Ok(None);
+        }
+
+        if self.ancestors.len() + 1 == self.depth {
+            if empty {
+                return self.read_record(start, end);
+            }
+
+            self.record = Some(start);
+            self.nested = 1;
+
+            return Ok(None);
+        }
+
+        if empty {
+            return Ok(None);
+        }
+
+        let tag = match read_start(&mut Reader::from_reader(markup), &mut vec![])? {
+            Some((tag, _)) => tag,
+            None => return Ok(None),
+        };
+        let name = read_name(tag.name(), options)?;
+
+        match self
+            .parser
+            .builder
+            .open_ancestor(&tag, name, start, self.ancestors.last())?
+        {
+            Some(ancestor) => self.ancestors.push(ancestor),
+            None => self.nested = 1,
+        }
+
+        Ok(None)
+    }
+
+    /// Read the record found between `start` and `end` in the buffer, or
+    /// `None` if the projection leaves it out.
+    fn read_record(&mut self, start: usize, end: usize) -> Result<Option<Value>, Error> {
+        let parser = &mut self.parser;
+        let bytes = self.buffer[start..end].to_vec();
+
+        // Limits apply to each record on its own.
+        parser.set_reader(Reader::from_reader(Cursor::new(bytes)));
+        parser.builder.elements = 0;
+
+        let (tag, empty) = match read_start(&mut parser.reader, &mut parser.buf)? {
+            Some(start) => start,
+            None => return Ok(None),
+        };
+
+        match parser.read_element(&tag, empty, 0, &self.ancestors)? {
+            Some(node) => {
+                let mut record = Map::new();
+                node.insert_into(&mut record)?;
+
+                Ok(Some(Value::Object(record)))
+            }
+            None => Ok(None),
+        }
+    }
+}
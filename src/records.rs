@@ -0,0 +1,203 @@
+use crate::{
+    error::Error,
+    html::is_void_element,
+    recover::Recovery,
+    to_json::{read_name, OpenElement, Parser},
+    *,
+};
+use quick_xml::{events::Event, Reader};
+use serde_json::{Map, Value};
+use std::{io::BufRead, sync::OnceLock};
+
+/// Split an absolute element path such as `/feed/entry` into its names.
+fn parse_path(path: &str) -> Result<Vec<String>, Error> {
+    let names = path
+        .strip_prefix('/')
+        .map(|names| {
+            names
+                .split('/')
+                .map(|name| name.to_owned())
+                .collect::<Vec<_>>()
+        })
+        .filter(|names| names.iter().all(|name| !name.is_empty()));
+
+    names.ok_or_else(|| Error::XmlInvalidPath(path.to_owned()))
+}
+
+/// An iterator over the elements at a path in a document, created by
+/// [`records`] or [`records_with_options`].
+pub struct Records<'o, B: BufRead> {
+    parser: Parser<'o, B>,
+    path: Vec<String>,
+    /// An error to yield before reading anything.
+    error: Option<Error>,
+    /// The elements enclosing the next record, with all of their
+    /// attributes.
+    ancestors: Vec<OpenElement>,
+    /// How deep the iterator is inside an element off the path.
+    skipped: usize,
+    finished: bool,
+}
+
+impl<B: BufRead> Records<'_, B> {
+    /// The name and attributes of each element enclosing the last record,
+    /// outermost first. Attributes are keyed by `$` and their name, as in
+    /// the records themselves.
+    pub fn ancestors(&self) -> impl Iterator<Item = (&str, &Map<String, Value>)> {
+        self.ancestors
+            .iter()
+            .map(|ancestor| (ancestor.name.as_str(), &ancestor.attributes))
+    }
+
+    fn next_record(&mut self) -> Result<Option<Value>, Error> {
+        if let Some(error) = self.error.take() {
+            return Err(error);
+        }
+
+        let path = &self.path;
+        let parser = &mut self.parser;
+        let options = parser.builder.options;
+
+        loop {
+            parser.buf.clear();
+
+            let position = parser.reader.buffer_position();
+            let start = match parser.reader.read_event(&mut parser.buf) {
+                Ok(Event::Start(e)) if self.skipped > 0 => {
+                    if !options.html || !is_void_element(e.name()) {
+                        self.skipped += 1;
+                    }
+
+                    continue;
+                }
+                Ok(Event::Start(e)) => (e.into_owned(), false),
+                Ok(Event::Empty(e)) if self.skipped == 0 => (e.into_owned(), true),
+                Ok(Event::End(e)) if options.html && is_void_element(e.name()) => continue,
+                Ok(Event::End(_)) => {
+                    match self.skipped {
+                        0 => {
+                            self.ancestors.pop();
+                        }
+                        _ => self.skipped -= 1,
+                    }
+
+                    continue;
+                }
+                Ok(Event::DocType(e)) => {
+                    let doctype = bytes_to_string(e.escaped())?;
+                    parser.builder.entities.declare_internal_subset(&doctype)?;
+                    continue;
+                }
+                Ok(Event::Eof) => match self.ancestors.is_empty() && self.skipped == 0 {
+                    true => return Ok(None),
+                    false => return Err(Error::XmlParseUnexpectedEof),
+                },
+                Ok(_) => continue,
+                Err(e) => return Err(Error::XmlQuickXmlError(e)),
+            };
+            let (start, empty) = start;
+            let depth = self.ancestors.len();
+            let name = read_name(start.name(), options)?;
+            let has_content = !empty && (!options.html || !is_void_element(start.name()));
+
+            if name != path[depth] {
+                if has_content {
+                    self.skipped = 1;
+                }
+
+                continue;
+            }
+
+            if depth + 1 == path.len() {
+                // Limits apply to each record on its own.
+                parser.start = position;
+                parser.builder.elements = 0;
+
+                let node = parser.read_element(&start, empty, position, &self.ancestors)?;
+
+                if let Some(node) = node {
+                    let mut record = Map::new();
+                    node.insert_into(&mut record)?;
+
+                    return Ok(Some(Value::Object(record)));
+                }
+
+                continue;
+            }
+
+            if !has_content {
+                continue;
+            }
+
+            match parser
+                .builder
+                .open_ancestor(&start, name, position, self.ancestors.last())?
+            {
+                Some(ancestor) => self.ancestors.push(ancestor),
+                None => self.skipped = 1,
+            }
+        }
+    }
+}
+
+impl<B: BufRead> Iterator for Records<'_, B> {
+    type Item = Result<Value, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.finished {
+            return None;
+        }
+
+        let record = self.next_record();
+
+        if !matches!(record, Ok(Some(_))) {
+            self.finished = true;
+        }
+
+        record.transpose()
+    }
+}
+
+/// Read the elements at `path`, such as `/feed/entry`, one at a time from a
+/// document too large to convert whole. See [`records_with_options`].
+pub fn records<B: BufRead>(reader: B, path: &str) -> Records<'static, B> {
+    static DEFAULT_OPTIONS: OnceLock<ReadOptions> = OnceLock::new();
+
+    records_with_options(
+        reader,
+        path,
+        DEFAULT_OPTIONS.get_or_init(ReadOptions::default),
+    )
+}
+
+/// Read the elements at `path` one at a time using the given options,
+/// yielding each as a JSON value holding just that element, in the same
+/// shape as `xml_fragments_to_json` gives it.
+///
+/// The path is absolute and names each element from the root down, such as
+/// `/feed/entry`. Only the record being read and the attributes of the
+/// elements enclosing it, available from [`Records::ancestors`], are kept in
+/// memory; everything else in the document is skipped. The `ParseLimits`
+/// apply to each record on its own, and the include and exclude patterns to
+/// the whole path of each element, leaving out the records they do not
+/// keep. The iterator ends after the first error.
+pub fn records_with_options<'o, B: BufRead>(
+    reader: B,
+    path: &str,
+    options: &'o ReadOptions,
+) -> Records<'o, B> {
+    let mut parser = Parser::new(Reader::from_reader(reader), options, Recovery::strict());
+    let (path, error) = match parse_path(path) {
+        Ok(path) => (path, parser.error.take()),
+        Err(error) => (vec![], Some(error)),
+    };
+
+    Records {
+        parser,
+        path,
+        error,
+        ancestors: vec![],
+        skipped: 0,
+        finished: false,
+    }
+}
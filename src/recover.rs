@@ -0,0 +1,49 @@
+use crate::error::Error;
+
+/// A problem in a document that was recovered from while reading it
+/// leniently.
+#[derive(Debug)]
+pub struct Diagnostic {
+    /// The byte offset in the input at which the problem was found.
+    pub position: usize,
+    /// The error that reading the document strictly fails with.
+    pub error: Error,
+}
+
+/// Decides whether a problem in a document is fatal, collecting it as a
+/// diagnostic instead when reading leniently.
+pub(crate) struct Recovery {
+    diagnostics: Option<Vec<Diagnostic>>,
+}
+
+impl Recovery {
+    pub(crate) fn strict() -> Self {
+        Recovery { diagnostics: None }
+    }
+
+    pub(crate) fn lenient() -> Self {
+        Recovery {
+            diagnostics: Some(vec![]),
+        }
+    }
+
+    pub(crate) fn is_lenient(&self) -> bool {
+        self.diagnostics.is_some()
+    }
+
+    /// Fail with `error` when reading strictly, or else record it and carry
+    /// on.
+    pub(crate) fn recover(&mut self, position: usize, error: Error) -> Result<(), Error> {
+        match &mut self.diagnostics {
+            None => Err(error),
+            Some(diagnostics) => {
+                diagnostics.push(Diagnostic { position, error });
+                Ok(())
+            }
+        }
+    }
+
+    pub(crate) fn into_diagnostics(self) -> Vec<Diagnostic> {
+        self.diagnostics.unwrap_or_default()
+    }
+}
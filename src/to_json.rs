@@ -1,141 +1,1169 @@
-use crate::{constants::*, error::Error, *};
+use crate::{
+    constants::*,
+    encoding::decode,
+    entities::Entities,
+    error::Error,
+    events::{build_document, Events},
+    html::is_void_element,
+    projection::{Projection, Selection},
+    recover::{Diagnostic, Recovery},
+    *,
+};
+use encoding_rs::UTF_8;
 use quick_xml::{
-    events::{attributes::Attributes, *},
+    events::{
+        attributes::{Attribute, Attributes},
+        *,
+    },
     Reader,
 };
 use serde_json::{Map, Value};
-use std::io::BufRead;
+use std::{
+    borrow::{BorrowMut, Cow},
+    io::BufRead,
+    marker::PhantomData,
+    mem,
+};
 
 fn is_string_whitespace(string: &str) -> bool {
     string.find(|c: char| !c.is_whitespace()).is_none()
 }
 
-fn parse_tag<B: BufRead>(
-    reader: &mut Reader<B>,
-    buf: &mut Vec<u8>,
-    root: bool,
+fn is_xml_whitespace(c: char) -> bool {
+    matches!(c, ' ' | '\t' | '\r' | '\n')
+}
+
+/// Apply a whitespace policy to a text node, returning `None` if the text
+/// should be dropped.
+pub(crate) fn apply_whitespace(string: String, whitespace: Whitespace) -> Option<String> {
+    match whitespace {
+        Whitespace::Preserve => Some(string).filter(|s| !s.is_empty()),
+        Whitespace::DropWhitespaceOnly if is_string_whitespace(&string) => None,
+        Whitespace::DropWhitespaceOnly => Some(string),
+        Whitespace::Trim => Some(string.trim_matches(is_xml_whitespace))
+            .filter(|s| !s.is_empty())
+            .map(|s| s.to_owned()),
+        Whitespace::Collapse => {
+            let words = string
+                .split(is_xml_whitespace)
+                .filter(|s| !s.is_empty())
+                .collect::<Vec<_>>();
+
+            match words.is_empty() {
+                true => None,
+                false => Some(words.join(" ")),
+            }
+        }
+    }
+}
+
+/// The attributes of a start tag, allowing the unquoted and valueless
+/// attributes of HTML when reading it.
+fn start_attributes<'s>(start: &'s BytesStart, options: &ReadOptions) -> Attributes<'s> {
+    match options.html {
+        true => start.html_attributes(),
+        false => start.attributes(),
+    }
+}
+
+/// The name of an element or attribute, which HTML matches regardless of
+/// case and so is lowercased when reading it.
+pub(crate) fn read_name(name: &[u8], options: &ReadOptions) -> Result<String, Error> {
+    match options.html {
+        true => bytes_to_string(&name.to_ascii_lowercase()),
+        false => bytes_to_string(name),
+    }
+}
+
+/// Whether whitespace is preserved inside an element, following its
+/// `xml:space` attribute or else inheriting from its parent. Malformed
+/// attributes are left to `read_attributes` to report.
+fn preserves_space(start: &BytesStart, parent: bool, options: &ReadOptions) -> bool {
+    for attribute in start_attributes(start, options).flatten() {
+        if attribute.key == b"xml:space" {
+            match &*attribute.value {
+                b"preserve" => return true,
+                b"default" => return false,
+                _ => {}
+            }
+        }
+    }
+
+    parent
+}
+
+/// Replace each literal whitespace character of a raw attribute value with a
+/// space, treating `\r\n` as one character. Character references are left
+/// alone so they survive unescaping.
+pub(crate) fn normalize_attribute_value(raw: &[u8]) -> Cow<'_, [u8]> {
+    if !raw.iter().any(|b| matches!(b, b'\t' | b'\r' | b'\n')) {
+        return Cow::Borrowed(raw);
+    }
+
+    let mut normalized = Vec::with_capacity(raw.len());
+    let mut bytes = raw.iter().peekable();
+
+    while let Some(&b) = bytes.next() {
+        match b {
+            b'\r' => {
+                bytes.next_if_eq(&&b'\n');
+                normalized.push(b' ');
+            }
+            b'\t' | b'\n' => normalized.push(b' '),
+            _ => normalized.push(b),
+        }
+    }
+
+    Cow::Owned(normalized)
+}
+
+/// Read an attribute value found at `position` in the input.
+fn attribute_value(
+    attribute: &Attribute,
+    position: usize,
+    options: &ReadOptions,
+    entities: &mut Entities,
+    recovery: &mut Recovery,
+) -> Result<String, Error> {
+    let value = match options.normalize_attribute_values {
+        true => normalize_attribute_value(&attribute.value),
+        false => Cow::Borrowed(&*attribute.value),
+    };
+
+    entities.unescape(&bytes_to_string(&value)?, position, recovery)
+}
+
+/// The quote character of each attribute of a start tag, in order.
+fn attribute_quotes(start: &BytesStart) -> String {
+    let mut quotes = String::new();
+    let mut bytes = start.iter().skip(start.name().len());
+
+    while let Some(&b) = bytes.next() {
+        if b == b'"' || b == b'\'' {
+            quotes.push(b as char);
+            bytes.find(|&&c| c == b);
+        }
+    }
+
+    quotes
+}
+
+/// Check an element starting at `depth` against the limits, counting it
+/// towards the total number of elements.
+pub(crate) fn check_element_limits(
+    start: &BytesStart,
+    depth: usize,
+    elements: &mut usize,
+    options: &ReadOptions,
+) -> Result<(), Error> {
+    let limits = &options.limits;
+    *elements += 1;
+
+    if limits.max_depth.is_some_and(|max| depth > max) {
+        return Err(Error::XmlParseDepthLimitExceeded);
+    }
+
+    if limits.max_elements.is_some_and(|max| *elements > max) {
+        return Err(Error::XmlParseElementLimitExceeded);
+    }
+
+    if let Some(max) = limits.max_attributes {
+        if start_attributes(start, options).count() > max {
+            return Err(Error::XmlParseAttributeLimitExceeded);
+        }
+    }
+
+    Ok(())
+}
+
+/// An element whose end tag has not been read yet.
+pub(crate) struct OpenElement {
+    pub(crate) name: String,
+    /// Attributes and, when preserving style, the attribute quotes. They come
+    /// before the text and children once the element is complete.
+    pub(crate) attributes: Map<String, Value>,
+    pub(crate) children: Map<String, Value>,
+    pub(crate) preserve_space: bool,
+    /// How the element relates to the include and exclude patterns.
+    pub(crate) selection: Selection,
+}
+
+/// Read the attributes of a start tag found at `position` in the input.
+/// When recovering from a malformed attribute, the attributes after it are
+/// dropped.
+fn read_attributes(
+    start: &BytesStart,
+    position: usize,
+    options: &ReadOptions,
+    entities: &mut Entities,
+    recovery: &mut Recovery,
 ) -> Result<Map<String, Value>, Error> {
-    let mut children = Map::new();
+    let mut attributes = Map::new();
 
-    loop {
-        let event = reader.read_event(buf);
+    for attribute in start_attributes(start, options) {
+        let attribute = match attribute {
+            Ok(attribute) => attribute,
+            Err(e) => {
+                recovery.recover(position, Error::XmlQuickXmlError(e))?;
+                break;
+            }
+        };
+        // The value borrows from the tag, which starts after the `<`.
+        let value_position =
+            position + 1 + (attribute.value.as_ptr() as usize - start.as_ptr() as usize);
 
-        let mut start_tag =
-            |name: &[u8], attributes: Attributes, map: Map<String, Value>| -> Result<(), Error> {
-                let mut map = map;
-
-                for attribute in attributes {
-                    let attribute = attribute.map_err(|e| Error::XmlQuickXmlError(e))?;
-                    map.insert(
-                        format!(
-                            "{}{}",
-                            ATTRIBUTE_START_CHARACTER,
-                            bytes_to_string(attribute.key)?
-                        ),
-                        Value::String(bytes_to_string(
-                            &attribute
-                                .unescaped_value()
-                                .map_err(|e| Error::XmlQuickXmlError(e))?,
-                        )?),
+        attributes.insert(
+            format!(
+                "{}{}",
+                ATTRIBUTE_START_CHARACTER,
+                read_name(attribute.key, options)?
+            ),
+            Value::String(attribute_value(
+                &attribute,
+                value_position,
+                options,
+                entities,
+                recovery,
+            )?),
+        );
+    }
+
+    if options.preserve_style {
+        let quotes = attribute_quotes(start);
+
+        if !quotes.is_empty() {
+            attributes.insert(QUOTES_STRING.to_owned(), Value::String(quotes));
+        }
+    }
+
+    Ok(attributes)
+}
+
+/// Add a complete element to the children of its parent.
+fn insert_element(
+    children: &mut Map<String, Value>,
+    name: String,
+    element: Map<String, Value>,
+) -> Result<(), Error> {
+    match children.get_mut(&name) {
+        None => {
+            children.insert(name, Value::Array(vec![Value::Object(element)]));
+        }
+        Some(value) => {
+            value
+                .as_array_mut()
+                .ok_or(Error::JsonParseUnexpectedArray)?
+                .push(Value::Object(element));
+        }
+    }
+
+    Ok(())
+}
+
+/// What became of an element once complete.
+pub(crate) enum Closed<N> {
+    /// It was added to its parent.
+    Nested,
+    /// It has no parent, so it is returned as a top level node, or `None`
+    /// if it is left out.
+    Outermost(Option<N>),
+}
+
+/// Receives what a `Parser` reads, with the elements open around it on
+/// `stack`.
+pub(crate) trait Sink {
+    /// What a complete top level node turns into.
+    type Node;
+
+    /// Whether reading should stop until what was passed to the sink so far
+    /// is used.
+    fn is_paused(&self) -> bool {
+        false
+    }
+
+    /// The element on top of `stack` was opened.
+    fn start(&mut self, stack: &mut [OpenElement]) -> Result<(), Error>;
+
+    /// An element without content was read.
+    fn element(
+        &mut self,
+        stack: &mut [OpenElement],
+        name: String,
+        element: Map<String, Value>,
+    ) -> Result<Option<Self::Node>, Error>;
+
+    /// Text was read, after the whitespace policy was applied.
+    fn text(
+        &mut self,
+        stack: &mut [OpenElement],
+        text: String,
+    ) -> Result<Option<Self::Node>, Error>;
+
+    /// An XML declaration was read.
+    fn decl(
+        &mut self,
+        stack: &mut [OpenElement],
+        decl: Map<String, Value>,
+    ) -> Result<Option<Self::Node>, Error>;
+
+    /// Complete the innermost open element, popping it off `stack`.
+    fn end(
+        &mut self,
+        stack: &mut Vec<OpenElement>,
+        options: &ReadOptions,
+    ) -> Result<Closed<Self::Node>, Error>;
+}
+
+/// Builds values, adding each node to its parent element as it is read.
+pub(crate) struct Tree;
+
+impl Sink for Tree {
+    type Node = Node;
+
+    fn start(&mut self, _: &mut [OpenElement]) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn element(
+        &mut self,
+        stack: &mut [OpenElement],
+        name: String,
+        element: Map<String, Value>,
+    ) -> Result<Option<Node>, Error> {
+        self.insert(stack, Node::Element(name, element))
+    }
+
+    fn text(&mut self, stack: &mut [OpenElement], text: String) -> Result<Option<Node>, Error> {
+        self.insert(stack, Node::Text(text))
+    }
+
+    fn decl(
+        &mut self,
+        stack: &mut [OpenElement],
+        decl: Map<String, Value>,
+    ) -> Result<Option<Node>, Error> {
+        self.insert(stack, Node::Decl(decl))
+    }
+
+    /// An element only kept on the way to those a projection includes is
+    /// left out when none of them turned up inside it.
+    fn end(
+        &mut self,
+        stack: &mut Vec<OpenElement>,
+        options: &ReadOptions,
+    ) -> Result<Closed<Node>, Error> {
+        let mut element = match stack.pop() {
+            Some(element) => element,
+            None => return Ok(Closed::Outermost(None)),
+        };
+        let empty = element.children.is_empty();
+        let node = match element.selection.included || !empty {
+            true => {
+                append_content(&mut element.attributes, &mut element.children);
+
+                if options.preserve_style && empty {
+                    element.attributes.insert(
+                        EMPTY_STYLE_STRING.to_owned(),
+                        Value::String(EXPANDED_STRING.to_owned()),
                     );
                 }
 
-                let key = bytes_to_string(name)?;
+                Some(Node::Element(element.name, element.attributes))
+            }
+            false => None,
+        };
 
-                match &mut children.get_mut(&key) {
-                    None => {
-                        children.insert(key, Value::Array(vec![Value::Object(map)]));
-                    }
-                    Some(value) => {
-                        value
-                            .as_array_mut()
-                            .ok_or(Error::JsonParseUnexpectedArray)?
-                            .push(Value::Object(map));
-                    }
+        match (stack.last_mut(), node) {
+            (Some(parent), Some(node)) => node.insert_into(&mut parent.children)?,
+            (Some(_), None) => {}
+            (None, node) => return Ok(Closed::Outermost(node)),
+        }
+
+        Ok(Closed::Nested)
+    }
+}
+
+impl Tree {
+    /// Add a node to the innermost open element, or else return it.
+    fn insert(&mut self, stack: &mut [OpenElement], node: Node) -> Result<Option<Node>, Error> {
+        match stack.last_mut() {
+            Some(parent) => node.insert_into(&mut parent.children).map(|_| None),
+            None => Ok(Some(node)),
+        }
+    }
+}
+
+pub(crate) fn read_decl(decl: &BytesDecl) -> Result<Map<String, Value>, Error> {
+    let mut map = Map::new();
+
+    map.insert(
+        "version".to_string(),
+        Value::String(bytes_to_string(
+            &decl.version().map_err(Error::XmlQuickXmlError)?,
+        )?),
+    );
+
+    if let Some(encoding) = decl.encoding() {
+        map.insert(
+            "encoding".to_string(),
+            Value::String(bytes_to_string(
+                &encoding.map_err(Error::XmlQuickXmlError)?,
+            )?),
+        );
+    }
+
+    if let Some(standalone) = decl.standalone() {
+        map.insert(
+            "standalone".to_string(),
+            Value::String(bytes_to_string(
+                &standalone.map_err(Error::XmlQuickXmlError)?,
+            )?),
+        );
+    }
+
+    Ok(map)
+}
+
+/// Add the content of an element to its attributes, with the text first so
+/// that a writer knows whether the element has text before reaching its
+/// children.
+pub(crate) fn append_content(element: &mut Map<String, Value>, content: &mut Map<String, Value>) {
+    if let Some((key, text)) = content.shift_remove_entry(&TEXT_CHARACTER.to_string()) {
+        element.insert(key, text);
+    }
+
+    element.append(content);
+}
+
+/// A node at the top level of a document.
+pub(crate) enum Node {
+    Element(String, Map<String, Value>),
+    Text(String),
+    Decl(Map<String, Value>),
+}
+
+impl Node {
+    /// Add the node to the map of a document's top level nodes.
+    pub(crate) fn insert_into(self, document: &mut Map<String, Value>) -> Result<(), Error> {
+        match self {
+            Node::Element(name, element) => insert_element(document, name, element)?,
+            // Text split up by child elements is joined.
+            Node::Text(text) => match document.get_mut(&TEXT_CHARACTER.to_string()) {
+                Some(Value::String(previous)) => previous.push_str(&text),
+                _ => {
+                    document.insert(TEXT_CHARACTER.to_string(), Value::String(text));
                 }
+            },
+            Node::Decl(decl) => {
+                document.insert(DECL_STRING.to_owned(), Value::Object(decl));
+            }
+        }
 
-                Ok(())
-            };
+        Ok(())
+    }
+}
 
-        match event {
-            Ok(Event::Start(ref e)) => {
-                let mut buf = vec![];
-                start_tag(
-                    e.name(),
-                    e.attributes(),
-                    parse_tag(reader, &mut buf, false)?,
-                )?;
-            }
-            Ok(Event::End(ref _e)) => {
-                break;
+/// What became of an element whose start tag was read.
+enum Opened {
+    /// It is left out along with its content.
+    Skipped,
+    /// It has no content, so it is complete.
+    Complete(String, Map<String, Value>),
+    /// Its content follows.
+    Open(OpenElement),
+}
+
+/// The state that building values depends on, kept apart from the reader
+/// so that both can be borrowed at once.
+pub(crate) struct Builder<'o> {
+    pub(crate) options: &'o ReadOptions,
+    pub(crate) recovery: Recovery,
+    pub(crate) entities: Entities<'o>,
+    pub(crate) projection: Projection,
+    /// The number of elements read in the current document.
+    pub(crate) elements: usize,
+}
+
+impl Builder<'_> {
+    /// How an element named `name` inside `parent` relates to the include
+    /// and exclude patterns, or `None` if it is left out.
+    fn select(&self, parent: Option<&OpenElement>, name: &str) -> Option<Selection> {
+        match parent {
+            Some(parent) => self.projection.select(&parent.selection, name),
+            None => self.projection.select(&self.projection.root(), name),
+        }
+    }
+
+    /// Open an element from its start tag, read at `position` inside
+    /// `parent` with `depth` elements open around it. `empty` tells whether
+    /// it is an empty element tag.
+    fn open_element(
+        &mut self,
+        start: &BytesStart,
+        empty: bool,
+        position: usize,
+        parent: Option<&OpenElement>,
+        depth: usize,
+    ) -> Result<Opened, Error> {
+        let options = self.options;
+        let name = read_name(start.name(), options)?;
+        let selection = match self.select(parent, &name) {
+            Some(selection) => selection,
+            None => return Ok(Opened::Skipped),
+        };
+
+        check_element_limits(start, depth + 1, &mut self.elements, options)?;
+
+        let mut attributes = match selection.included {
+            true => read_attributes(
+                start,
+                position,
+                options,
+                &mut self.entities,
+                &mut self.recovery,
+            )?,
+            false => Map::new(),
+        };
+
+        if empty || (options.html && is_void_element(start.name())) {
+            // An element on the way to those a projection includes is left
+            // out once it turns out to have no content.
+            if !selection.included {
+                return Ok(Opened::Skipped);
             }
-            Ok(Event::Empty(ref e)) => {
-                start_tag(e.name(), e.attributes(), Map::new())?;
+
+            if empty && options.preserve_style {
+                attributes.insert(
+                    EMPTY_STYLE_STRING.to_owned(),
+                    Value::String(SELF_CLOSING_STRING.to_owned()),
+                );
             }
-            Ok(Event::Text(ref e)) => {
-                let string = e
-                    .unescape_and_decode(&reader)
-                    .map_err(|e| Error::XmlQuickXmlError(e))?;
 
-                if is_string_whitespace(&string) {
-                    continue;
-                }
+            return Ok(Opened::Complete(name, attributes));
+        }
+
+        let parent_preserves_space = parent.is_some_and(|p| p.preserve_space);
+
+        Ok(Opened::Open(OpenElement {
+            name,
+            attributes,
+            children: Map::new(),
+            preserve_space: preserves_space(start, parent_preserves_space, options),
+            selection,
+        }))
+    }
+
+    /// Open an element named `name` enclosing the records being read, from
+    /// its start tag read at `position` inside `parent`, with all of its
+    /// attributes, or `None` if the projection leaves it out.
+    pub(crate) fn open_ancestor(
+        &mut self,
+        start: &BytesStart,
+        name: String,
+        position: usize,
+        parent: Option<&OpenElement>,
+    ) -> Result<Option<OpenElement>, Error> {
+        let options = self.options;
+        let selection = match self.select(parent, &name) {
+            Some(selection) => selection,
+            None => return Ok(None),
+        };
+        let parent_preserves_space = parent.is_some_and(|p| p.preserve_space);
+
+        Ok(Some(OpenElement {
+            name,
+            attributes: read_attributes(
+                start,
+                position,
+                options,
+                &mut self.entities,
+                &mut self.recovery,
+            )?,
+            children: Map::new(),
+            preserve_space: preserves_space(start, parent_preserves_space, options),
+            selection,
+        }))
+    }
+}
 
-                children.insert(TEXT_CHARACTER.to_string(), Value::String(string));
+/// Reads XML one top level node at a time, keeping the state that spans
+/// the nodes of a document.
+///
+/// Open elements are kept on an explicit stack rather than the native one,
+/// so the depth of the document only costs heap memory, and a single event
+/// buffer is reused for the whole input.
+///
+/// When reading HTML or when `recovery` is lenient, end tags are matched
+/// against the open elements here rather than by the reader: when recovering,
+/// an end tag closes every element opened after the one it names, and end
+/// tags naming no open element are ignored.
+///
+/// The reader is usually owned, but may be borrowed from a caller reading
+/// the rest of the input themselves.
+pub(crate) struct Parser<'o, B: BufRead, R = Reader<B>> {
+    pub(crate) reader: R,
+    pub(crate) buf: Vec<u8>,
+    pub(crate) builder: Builder<'o>,
+    /// Where the current document starts in the input.
+    pub(crate) start: usize,
+    match_end_tags: bool,
+    /// An error in the options, returned before reading anything.
+    pub(crate) error: Option<Error>,
+    input: PhantomData<B>,
+}
+
+impl<'o, B: BufRead> Parser<'o, B> {
+    pub(crate) fn new(mut reader: Reader<B>, options: &'o ReadOptions, recovery: Recovery) -> Self {
+        // HTML end tags match regardless of case, and recovering needs to see
+        // the ones that do not match, so the reader only checks them otherwise.
+        reader.check_end_names(!options.html && !recovery.is_lenient());
+        Parser::with_reader(reader, options, recovery)
+    }
+
+    /// Carry on reading from `reader`, keeping the state of the document
+    /// read so far, such as the entities it declared.
+    pub(crate) fn set_reader(&mut self, mut reader: Reader<B>) {
+        reader.check_end_names(!self.match_end_tags);
+        self.reader = reader;
+        self.start = 0;
+    }
+}
+
+impl<'o, B: BufRead, R: BorrowMut<Reader<B>>> Parser<'o, B, R> {
+    /// Read from `reader` as it is set up, leaving it to check end tags
+    /// when `new` would.
+    pub(crate) fn with_reader(reader: R, options: &'o ReadOptions, recovery: Recovery) -> Self {
+        let match_end_tags = options.html || recovery.is_lenient();
+        let (projection, error) = match Projection::new(&options.include, &options.exclude) {
+            Ok(projection) => (projection, None),
+            Err(error) => (Projection::default(), Some(error)),
+        };
+
+        Parser {
+            reader,
+            buf: vec![],
+            builder: Builder {
+                options,
+                recovery,
+                entities: Entities::new(
+                    &options.entities,
+                    options.html,
+                    options.limits.max_entity_expansion,
+                ),
+                projection,
+                elements: 0,
+            },
+            start: 0,
+            match_end_tags,
+            error,
+            input: PhantomData,
+        }
+    }
+
+    /// Start reading a new document, forgetting the entities declared by the
+    /// previous one and counting towards the limits afresh.
+    fn next_document(&mut self) {
+        let options = self.builder.options;
+
+        self.builder.entities = Entities::new(
+            &options.entities,
+            options.html,
+            options.limits.max_entity_expansion,
+        );
+        self.builder.elements = 0;
+        self.start = self.reader.borrow().buffer_position();
+    }
+
+    /// Read the next top level node, or `None` at the end of the input.
+    /// Comments, processing instructions, text dropped by the whitespace
+    /// policy and elements left out by the projection are skipped.
+    fn next_node(&mut self) -> Result<Option<Node>, Error> {
+        self.read_node(&mut Tree, &mut vec![], 0)
+    }
+
+    /// Read until the elements already open on `stack`, which are nested
+    /// `depth` elements deep, are complete and return the outermost one, or
+    /// `None` if it is left out. With no elements open, read the next top
+    /// level node like `next_node`, passing everything read to `sink`. Also
+    /// returns `None` as soon as the sink is paused, leaving the elements
+    /// open on `stack` to carry on with.
+    pub(crate) fn read_node<S: Sink>(
+        &mut self,
+        sink: &mut S,
+        stack: &mut Vec<OpenElement>,
+        depth: usize,
+    ) -> Result<Option<S::Node>, Error> {
+        if let Some(error) = self.error.take() {
+            return Err(error);
+        }
+
+        let nested = !stack.is_empty();
+        let Parser {
+            reader,
+            buf,
+            builder,
+            start,
+            match_end_tags,
+            ..
+        } = self;
+        let reader = reader.borrow_mut();
+        let options = builder.options;
+        let limits = &options.limits;
+
+        loop {
+            if sink.is_paused() {
+                return Ok(None);
             }
-            Ok(Event::Comment(ref _e)) => {}
-            Ok(Event::CData(ref _e)) => {}
-            Ok(Event::Decl(ref e)) => {
-                let mut map = Map::new();
 
-                map.insert(
-                    "version".to_string(),
-                    Value::String(bytes_to_string(
-                        &e.version().map_err(|e| Error::XmlQuickXmlError(e))?,
-                    )?),
-                );
+            buf.clear();
 
-                if let Some(encoding) = e.encoding() {
-                    map.insert(
-                        "encoding".to_string(),
-                        Value::String(bytes_to_string(
-                            &encoding.map_err(|e| Error::XmlQuickXmlError(e))?,
-                        )?),
-                    );
+            let position = reader.buffer_position();
+            let event = reader.read_event(buf);
+
+            if limits
+                .max_bytes
+                .is_some_and(|max| reader.buffer_position() - *start > max)
+            {
+                return Err(Error::XmlParseSizeLimitExceeded);
+            }
+
+            match event {
+                Ok(Event::Start(ref e)) | Ok(Event::Empty(ref e)) => {
+                    let empty = matches!(event, Ok(Event::Empty(_)));
+                    let opened = builder.open_element(
+                        e,
+                        empty,
+                        position,
+                        stack.last(),
+                        depth + stack.len(),
+                    )?;
+
+                    match opened {
+                        Opened::Skipped => {
+                            if !empty && (!options.html || !is_void_element(e.name())) {
+                                skip_element(reader, buf, *start, builder)?;
+                            }
+                        }
+                        Opened::Open(element) => {
+                            stack.push(element);
+                            sink.start(stack)?;
+                        }
+                        Opened::Complete(name, element) => {
+                            if let Some(node) = sink.element(stack, name, element)? {
+                                return Ok(Some(node));
+                            }
+                        }
+                    }
                 }
+                // The end tag of an HTML void element is ignored like its
+                // missing one.
+                Ok(Event::End(ref e)) if options.html && is_void_element(e.name()) => {}
+                Ok(Event::End(ref e)) => {
+                    let name = read_name(e.name(), options)?;
+                    let open = match match_end_tags {
+                        true => stack.iter().rposition(|open| open.name == name),
+                        false => stack.len().checked_sub(1),
+                    };
+                    let recovery = &mut builder.recovery;
 
-                if let Some(standalone) = e.standalone() {
-                    map.insert(
-                        "standalone".to_string(),
-                        Value::String(bytes_to_string(
-                            &standalone.map_err(|e| Error::XmlQuickXmlError(e))?,
-                        )?),
-                    );
+                    match open {
+                        None => {
+                            recovery.recover(position, Error::XmlParseUnexpectedEndTag(name))?
+                        }
+                        Some(i) => {
+                            while stack.len() > i + 1 {
+                                let unclosed = stack[stack.len() - 1].name.clone();
+                                recovery
+                                    .recover(position, Error::XmlParseUnclosedElement(unclosed))?;
+                                sink.end(stack, options)?;
+                            }
+
+                            if let Closed::Outermost(node) = sink.end(stack, options)? {
+                                if node.is_some() || nested {
+                                    return Ok(node);
+                                }
+                            }
+                        }
+                    }
                 }
+                Ok(Event::Text(ref e)) => {
+                    // Only the elements on the way to those a projection
+                    // includes are kept without being included themselves.
+                    if stack.last().is_some_and(|p| !p.selection.included) {
+                        continue;
+                    }
+
+                    let string = builder.entities.unescape(
+                        &bytes_to_string(e.escaped())?,
+                        position,
+                        &mut builder.recovery,
+                    )?;
+
+                    if limits
+                        .max_text_length
+                        .is_some_and(|max| string.chars().count() > max)
+                    {
+                        return Err(Error::XmlParseTextLimitExceeded);
+                    }
+
+                    let whitespace = match stack.last().is_some_and(|p| p.preserve_space) {
+                        true => Whitespace::Preserve,
+                        false => options.whitespace,
+                    };
+
+                    if let Some(string) = apply_whitespace(string, whitespace) {
+                        if let Some(node) = sink.text(stack, string)? {
+                            return Ok(Some(node));
+                        }
+                    }
+                }
+                Ok(Event::Comment(ref _e)) => {}
+                Ok(Event::CData(ref _e)) => {}
+                Ok(Event::Decl(ref e)) => {
+                    if let Some(node) = sink.decl(stack, read_decl(e)?)? {
+                        return Ok(Some(node));
+                    }
+                }
+                Ok(Event::PI(ref _e)) => {}
+                Ok(Event::DocType(ref e)) => {
+                    let doctype = bytes_to_string(e.escaped())?;
+                    builder.entities.declare_internal_subset(&doctype)?;
+                }
+                Ok(Event::Eof) => {
+                    let recovery = &mut builder.recovery;
+
+                    if !stack.is_empty() && !recovery.is_lenient() {
+                        return Err(Error::XmlParseUnexpectedEof);
+                    }
+
+                    while let Some(open) = stack.last() {
+                        recovery
+                            .recover(position, Error::XmlParseUnclosedElement(open.name.clone()))?;
 
-                children.insert(DECL_STRING.to_owned(), Value::Object(map));
+                        if let Closed::Outermost(node) = sink.end(stack, options)? {
+                            if node.is_some() || nested {
+                                return Ok(node);
+                            }
+                        }
+                    }
+
+                    return Ok(None);
+                }
+                // The reader returns nothing but `Eof` after an error, so
+                // recovering from one ends the input.
+                Err(e) => builder
+                    .recovery
+                    .recover(reader.buffer_position(), Error::XmlQuickXmlError(e))?,
             }
-            Ok(Event::PI(ref _e)) => {}
-            Ok(Event::DocType(ref _e)) => {}
-            Ok(Event::Eof) => {
-                if root {
-                    break;
+        }
+    }
+
+    /// Read the element started by `start`, which was read at `position`
+    /// inside the elements `ancestors`, and return it as a top level node, or
+    /// `None` if the projection leaves it out. `empty` tells whether it is an
+    /// empty element tag.
+    pub(crate) fn read_element(
+        &mut self,
+        start: &BytesStart,
+        empty: bool,
+        position: usize,
+        ancestors: &[OpenElement],
+    ) -> Result<Option<Node>, Error> {
+        let depth = ancestors.len();
+
+        match self
+            .builder
+            .open_element(start, empty, position, ancestors.last(), depth)?
+        {
+            Opened::Skipped => {
+                let options = self.builder.options;
+
+                if !empty && (!options.html || !is_void_element(start.name())) {
+                    skip_element(
+                        self.reader.borrow_mut(),
+                        &mut self.buf,
+                        self.start,
+                        &mut self.builder,
+                    )?;
                 }
 
-                return Err(Error::XmlParseUnexpectedEof);
+                Ok(None)
             }
-            Err(e) => return Err(Error::XmlQuickXmlError(e)),
+            Opened::Complete(name, element) => Ok(Some(Node::Element(name, element))),
+            Opened::Open(element) => self.read_node(&mut Tree, &mut vec![element], depth),
         }
+    }
+}
+
+/// Read past the content and end tag of an element whose start tag was
+/// just read, without building anything from it. `start` is where the
+/// current document starts in the input.
+fn skip_element<B: BufRead>(
+    reader: &mut Reader<B>,
+    buf: &mut Vec<u8>,
+    start: usize,
+    builder: &mut Builder,
+) -> Result<(), Error> {
+    let options = builder.options;
+    let mut depth = 1;
 
+    while depth > 0 {
         buf.clear();
+
+        let event = reader.read_event(buf);
+
+        if options
+            .limits
+            .max_bytes
+            .is_some_and(|max| reader.buffer_position() - start > max)
+        {
+            return Err(Error::XmlParseSizeLimitExceeded);
+        }
+
+        match event {
+            Ok(Event::Start(ref e)) if !options.html || !is_void_element(e.name()) => depth += 1,
+            Ok(Event::End(ref e)) if !options.html || !is_void_element(e.name()) => depth -= 1,
+            // Leave the end of the input for the caller to deal with.
+            Ok(Event::Eof) => break,
+            Ok(_) => {}
+            Err(e) => builder
+                .recovery
+                .recover(reader.buffer_position(), Error::XmlQuickXmlError(e))?,
+        }
     }
 
-    Ok(children)
+    Ok(())
 }
 
-/// Convert an XML string to a JSON value.
+/// Convert an XML string to a JSON value. See [`xml_to_json_with_options`].
 pub fn xml_to_json(xml: &str) -> Result<Value, Error> {
-    let mut buf = vec![];
-    let mut reader = Reader::from_str(xml);
-    Ok(Value::Object(parse_tag(&mut reader, &mut buf, true)?))
+    xml_to_json_with_options(xml, &ReadOptions::default())
+}
+
+/// Convert an XML string to a JSON value using the given options.
+///
+/// Any depth of nesting is read without recursing, but serde_json both
+/// drops and serializes a `Value` recursively, so doing either with the
+/// value of a very deep document can overflow the caller's stack.
+/// `ParseLimits::max_depth` rejects such documents up front.
+pub fn xml_to_json_with_options(xml: &str, options: &ReadOptions) -> Result<Value, Error> {
+    let parser = Parser::new(Reader::from_str(xml), options, Recovery::strict());
+    Ok(Value::Object(build_document(Events::new(parser), options)?))
+}
+
+/// Convert an XML string to a JSON value, recovering from malformed markup
+/// rather than failing, and return the value with a diagnostic for each
+/// problem recovered from.
+///
+/// Elements left open at the end of the input are closed, a `&` that does
+/// not start a valid reference is kept as literal text, and an end tag that
+/// does not match the innermost open element closes the element it names,
+/// or is ignored if no such element is open. Other malformed markup ends the
+/// document where it is found. Exceeding a `ParseLimits` limit is still an
+/// error.
+pub fn xml_to_json_lenient(
+    xml: &str,
+    options: &ReadOptions,
+) -> Result<(Value, Vec<Diagnostic>), Error> {
+    let parser = Parser::new(Reader::from_str(xml), options, Recovery::lenient());
+    let mut events = Events::new(parser);
+    let document = build_document(&mut events, options)?;
+
+    Ok((
+        Value::Object(document),
+        events.into_parser().builder.recovery.into_diagnostics(),
+    ))
+}
+
+/// Convert XML made of any number of top level nodes, such as several
+/// elements with text between them, to one JSON value per node in document
+/// order. Each value is an object with the single key the node has in the
+/// jxon conventions, so that elements with the same name are not merged.
+pub fn xml_fragments_to_json(xml: &str, options: &ReadOptions) -> Result<Vec<Value>, Error> {
+    let mut parser = Parser::new(Reader::from_str(xml), options, Recovery::strict());
+    let mut nodes = vec![];
+
+    while let Some(node) = parser.next_node()? {
+        let mut map = Map::new();
+        node.insert_into(&mut map)?;
+        nodes.push(Value::Object(map));
+    }
+
+    Ok(nodes)
+}
+
+/// An iterator over the documents in a stream of concatenated XML
+/// documents, created by [`xml_documents_to_json`].
+pub struct Documents<'o, B: BufRead> {
+    parser: Parser<'o, B>,
+    finished: bool,
+}
+
+impl<B: BufRead> Iterator for Documents<'_, B> {
+    type Item = Result<Value, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.finished {
+            return None;
+        }
+
+        let mut document = Map::new();
+        self.parser.next_document();
+
+        loop {
+            let node = match self.parser.next_node() {
+                Ok(Some(node)) => node,
+                Ok(None) => {
+                    self.finished = true;
+
+                    return match document.is_empty() {
+                        true => None,
+                        false => Some(Ok(Value::Object(document))),
+                    };
+                }
+                Err(e) => {
+                    self.finished = true;
+                    return Some(Err(e));
+                }
+            };
+            let is_element = matches!(node, Node::Element(..));
+
+            // Whitespace kept by the whitespace policy only separates the
+            // documents, so it belongs to none of them.
+            if let Node::Text(text) = &node {
+                if is_string_whitespace(text) {
+                    continue;
+                }
+            }
+
+            if let Err(e) = node.insert_into(&mut document) {
+                self.finished = true;
+                return Some(Err(e));
+            }
+
+            if is_element {
+                return Some(Ok(Value::Object(document)));
+            }
+        }
+    }
+}
+
+/// Read a stream of concatenated XML documents, such as a log file or a
+/// message queue, yielding one JSON value per document as soon as its root
+/// element ends.
+///
+/// Each document may start with its own XML declaration, and everything
+/// before a root element belongs to that element's document, except for
+/// whitespace, which is dropped whatever the whitespace policy. Entities
+/// declared in a document and the `ParseLimits` apply to that document
+/// only. The iterator ends after the first error.
+pub fn xml_documents_to_json<B: BufRead>(reader: B, options: &ReadOptions) -> Documents<'_, B> {
+    Documents {
+        parser: Parser::new(Reader::from_reader(reader), options, Recovery::strict()),
+        finished: false,
+    }
+}
+
+/// Convert the element whose start tag was just read from `reader`, such as
+/// the payload inside an envelope read with quick-xml directly. See
+/// [`parse_element_with_options`].
+pub fn parse_element<B: BufRead>(
+    reader: &mut Reader<B>,
+    start: &BytesStart,
+    buf: &mut Vec<u8>,
+) -> Result<Value, Error> {
+    parse_element_with_options(reader, start, buf, &ReadOptions::default())
+}
+
+/// Convert the element whose start tag was just read from `reader` using
+/// the given options, returning a JSON value holding just that element, in
+/// the same shape as `records_with_options` gives it, or an empty object if
+/// the projection leaves it out. `buf` is used to read the content of the
+/// element, so it cannot be the buffer `start` borrows from.
+///
+/// The start tag must come from an `Event::Start`: an `Event::Empty` has
+/// no content to read, unless the reader expands empty elements. Reading
+/// stops right after the matching end tag, leaving the rest of the input to
+/// the caller. Each end tag must name the element it closes, so given the
+/// start tag of an empty element, reading fails at the end tag of the
+/// element around it rather than carrying on past it. End tags are also
+/// checked by the reader if it is set up to, which should not be the case
+/// when reading HTML.
+pub fn parse_element_with_options<B: BufRead>(
+    reader: &mut Reader<B>,
+    start: &BytesStart,
+    buf: &mut Vec<u8>,
+    options: &ReadOptions,
+) -> Result<Value, Error> {
+    let mut parser = Parser::with_reader(reader, options, Recovery::strict());
+
+    if let Some(error) = parser.error.take() {
+        return Err(error);
+    }
+
+    // The reader only checks end tags against the start tags it read
+    // itself, which does not include `start` if it was an empty element tag.
+    parser.match_end_tags = true;
+
+    let position = parser.reader.buffer_position();
+    parser.start = position;
+    parser.buf = mem::take(buf);
+
+    let node = parser.read_element(start, false, position, &[]);
+    *buf = mem::take(&mut parser.buf);
+
+    let mut element = Map::new();
+
+    if let Some(node) = node? {
+        node.insert_into(&mut element)?;
+    }
+
+    Ok(Value::Object(element))
+}
+
+/// Convert an XML document in any supported encoding to a JSON value.
+///
+/// The encoding is taken from the byte order mark or else the XML
+/// declaration, defaulting to UTF-8. When it is not UTF-8 and the declaration
+/// does not name it, it is recorded in the declaration object, which is
+/// created if the document has none.
+pub fn xml_bytes_to_json(xml: &[u8]) -> Result<Value, Error> {
+    xml_bytes_to_json_with_options(xml, &ReadOptions::default())
+}
+
+/// Convert an XML document in any supported encoding to a JSON value using
+/// the given options.
+pub fn xml_bytes_to_json_with_options(xml: &[u8], options: &ReadOptions) -> Result<Value, Error> {
+    let (xml, encoding) = decode(xml)?;
+    let mut value = xml_to_json_with_options(&xml, options)?;
+
+    if encoding != UTF_8 {
+        if let Value::Object(map) = &mut value {
+            match map.get_mut(DECL_STRING) {
+                Some(Value::Object(decl)) => {
+                    if !decl.contains_key("encoding") {
+                        decl.insert(
+                            "encoding".to_owned(),
+                            Value::String(encoding.name().to_owned()),
+                        );
+                    }
+                }
+                _ => {
+                    let mut decl = Map::new();
+                    decl.insert("version".to_owned(), Value::String("1.0".to_owned()));
+                    decl.insert(
+                        "encoding".to_owned(),
+                        Value::String(encoding.name().to_owned()),
+                    );
+
+                    let mut with_decl = Map::new();
+                    with_decl.insert(DECL_STRING.to_owned(), Value::Object(decl));
+                    with_decl.append(map);
+                    *map = with_decl;
+                }
+            }
+        }
+    }
+
+    Ok(value)
 }
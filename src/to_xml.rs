@@ -1,136 +1,609 @@
-use crate::{constants::*, error::Error, *};
-use quick_xml::{events::*, Writer};
-use serde_json::Value;
-use std::io::Cursor;
+use crate::{
+    constants::*,
+    encoding::encode,
+    error::Error,
+    format::{ElementStyle, Formatter},
+    *,
+};
+use quick_xml::events::{attributes::Attribute, *};
+use serde::de::{self, DeserializeSeed, Deserializer, MapAccess, SeqAccess, Visitor};
+use serde_json::{Map, Value};
+use std::{borrow::Cow, collections::VecDeque, fmt, io::Write};
 
-fn is_attribute_property_name(name: &str) -> bool {
+pub(crate) fn is_attribute_property_name(name: &str) -> bool {
     name.find(ATTRIBUTE_START_CHARACTER) == Some(0)
 }
 
-fn is_decl(name: &str) -> bool {
+/// Escape an attribute value so that it reads back unchanged. Besides the
+/// markup characters, tabs and line breaks are written as character
+/// references because a parser normalizes literal ones to spaces.
+pub(crate) fn escape_attribute_value(value: &str) -> Vec<u8> {
+    let mut escaped = Vec::with_capacity(value.len());
+
+    for b in value.bytes() {
+        match b {
+            b'<' => escaped.extend_from_slice(b"&lt;"),
+            b'>' => escaped.extend_from_slice(b"&gt;"),
+            b'&' => escaped.extend_from_slice(b"&amp;"),
+            b'\'' => escaped.extend_from_slice(b"&apos;"),
+            b'"' => escaped.extend_from_slice(b"&quot;"),
+            b'\t' => escaped.extend_from_slice(b"&#9;"),
+            b'\n' => escaped.extend_from_slice(b"&#10;"),
+            b'\r' => escaped.extend_from_slice(b"&#13;"),
+            _ => escaped.push(b),
+        }
+    }
+
+    escaped
+}
+
+pub(crate) fn is_decl(name: &str) -> bool {
     name == DECL_STRING
 }
 
-fn write_value(writer: &mut Writer<Cursor<Vec<u8>>>, value: Value) -> Result<(), Error> {
-    match value {
-        Value::Null => return Err(Error::JsonParseUnexpectedNull),
-        Value::Bool(_) => return Err(Error::JsonParseUnexpectedBool),
-        Value::Number(_) => return Err(Error::JsonParseUnexpectedNumber),
-        Value::String(string) => {
-            writer
-                .write_event(Event::Text(BytesText::from_plain_str(&string)))
-                .map_err(|e| Error::XmlQuickXmlError(e))?;
-        }
-        Value::Array(_) => return Err(Error::JsonParseUnexpectedArray),
-        Value::Object(map) => {
-            for (key, value) in map {
-                if is_attribute_property_name(&key) {
-                    continue;
-                }
+pub(crate) fn is_style_property_name(name: &str) -> bool {
+    name == EMPTY_STYLE_STRING || name == QUOTES_STRING
+}
 
-                if is_decl(&key) {
-                    writer
-                        .write_event(Event::Decl(BytesDecl::new(
-                            value
-                                .get("version")
-                                .ok_or(Error::JsonParseDeclMissingVersion)?
-                                .as_str()
-                                .ok_or(Error::JsonParseInvalidDecl)?
-                                .as_bytes(),
-                            match value.get("encoding") {
-                                Some(v) => {
-                                    Some(v.as_str().ok_or(Error::JsonParseInvalidDecl)?.as_bytes())
-                                }
-                                None => None,
-                            },
-                            match value.get("standalone") {
-                                Some(v) => {
-                                    Some(v.as_str().ok_or(Error::JsonParseInvalidDecl)?.as_bytes())
-                                }
-                                None => None,
-                            },
-                        )))
-                        .map_err(|e| Error::XmlQuickXmlError(e))?;
-
-                    continue;
-                }
+pub(crate) fn element_style(object: &Map<String, Value>) -> Result<ElementStyle<'_>, Error> {
+    Ok(ElementStyle {
+        has_text: object.contains_key(&TEXT_CHARACTER.to_string()),
+        self_close: match object.get(EMPTY_STYLE_STRING) {
+            None => None,
+            Some(Value::String(style)) if style == SELF_CLOSING_STRING => Some(true),
+            Some(Value::String(style)) if style == EXPANDED_STRING => Some(false),
+            Some(_) => return Err(Error::JsonParseInvalidStyle),
+        },
+        quotes: match object.get(QUOTES_STRING) {
+            None => b"",
+            Some(Value::String(quotes)) if quotes.bytes().all(|b| b == b'"' || b == b'\'') => {
+                quotes.as_bytes()
+            }
+            Some(_) => return Err(Error::JsonParseInvalidStyle),
+        },
+    })
+}
 
-                match value {
-                    Value::String(_) => write_value(writer, value)?,
-                    Value::Array(values) => {
-                        for value in values {
-                            let has_children = match &value {
-                                Value::Null
-                                | Value::Bool(_)
-                                | Value::Number(_)
-                                | Value::String(_) => false,
-                                Value::Array(array) => array.is_empty(),
-                                Value::Object(object) => object
-                                    .iter()
-                                    .find(|(key, _)| !is_attribute_property_name(key))
-                                    .is_some(),
-                            };
-                            let mut bytes_start = BytesStart::borrowed(key.as_bytes(), key.len());
-
-                            match &value {
-                                Value::Object(object) => {
-                                    for (key, value) in object.iter() {
-                                        if is_attribute_property_name(key) {
-                                            bytes_start.push_attribute((
-                                                key.get(1..)
-                                                    .ok_or(Error::JsonParseInvalidAttributeName)?,
-                                                match value {
-                                                    Value::String(string) => string,
-                                                    _ => {
-                                                        return Err(
-                                                            Error::JsonParseInvalidAttributeValue,
-                                                        )
-                                                    }
-                                                }
-                                                .as_str(),
-                                            ));
-                                        }
-                                    }
-                                }
-                                _ => return Err(Error::JsonParseExpectedObject),
-                            }
-
-                            if !has_children {
-                                writer
-                                    .write_event(Event::Empty(bytes_start))
-                                    .map_err(|e| Error::XmlQuickXmlError(e))?;
-                            } else {
-                                writer
-                                    .write_event(Event::Start(bytes_start))
-                                    .map_err(|e| Error::XmlQuickXmlError(e))?;
-
-                                write_value(writer, value)?;
-
-                                writer
-                                    .write_event(Event::End(BytesEnd::borrowed(key.as_bytes())))
-                                    .map_err(|e| Error::XmlQuickXmlError(e))?;
-                            }
-                        }
-                    }
-                    _ => return Err(Error::JsonParseExpectedArray),
-                }
+/// The declaration held by the `DECL_STRING` (`"#"`) key.
+pub(crate) fn read_decl(value: &Value) -> Result<BytesDecl<'static>, Error> {
+    Ok(BytesDecl::new(
+        value
+            .get("version")
+            .ok_or(Error::JsonParseDeclMissingVersion)?
+            .as_str()
+            .ok_or(Error::JsonParseInvalidDecl)?
+            .as_bytes(),
+        match value.get("encoding") {
+            Some(v) => Some(v.as_str().ok_or(Error::JsonParseInvalidDecl)?.as_bytes()),
+            None => None,
+        },
+        match value.get("standalone") {
+            Some(v) => Some(v.as_str().ok_or(Error::JsonParseInvalidDecl)?.as_bytes()),
+            None => None,
+        },
+    ))
+}
+
+pub(crate) fn write_decl<W: Write>(writer: &mut Formatter<W>, value: &Value) -> Result<(), Error> {
+    writer.decl(&read_decl(value)?)
+}
+
+pub(crate) fn start_tag<'v>(
+    name: &'v str,
+    object: &'v Map<String, Value>,
+) -> Result<BytesStart<'v>, Error> {
+    let mut bytes_start = BytesStart::borrowed(name.as_bytes(), name.len());
+
+    for (key, value) in object.iter() {
+        if is_attribute_property_name(key) {
+            bytes_start.push_attribute(Attribute {
+                key: key
+                    .get(1..)
+                    .ok_or(Error::JsonParseInvalidAttributeName)?
+                    .as_bytes(),
+                value: Cow::Owned(escape_attribute_value(match value {
+                    Value::String(string) => string,
+                    _ => return Err(Error::JsonParseInvalidAttributeValue),
+                })),
+            });
+        }
+    }
+
+    Ok(bytes_start)
+}
+
+/// How an entry of an object is written, going by its key.
+pub(crate) enum Entry {
+    /// An attribute or style property, which goes in the start tag.
+    Attribute,
+    /// The XML declaration.
+    Decl,
+    /// Text, or an array of the elements named by the key.
+    Content,
+}
+
+pub(crate) fn entry(key: &str) -> Entry {
+    if is_attribute_property_name(key) || is_style_property_name(key) {
+        Entry::Attribute
+    } else if is_decl(key) {
+        Entry::Decl
+    } else {
+        Entry::Content
+    }
+}
+
+/// Receives the events of a document along with how each element is
+/// written.
+pub(crate) trait Output {
+    fn event(&mut self, event: Event, style: &ElementStyle) -> Result<(), Error>;
+}
+
+impl<W: Write> Output for Formatter<'_, W> {
+    fn event(&mut self, event: Event, style: &ElementStyle) -> Result<(), Error> {
+        match event {
+            Event::Decl(decl) => self.decl(&decl),
+            Event::Start(start) => self.start(&start, style),
+            Event::Empty(start) => self.empty(&start, style),
+            Event::Text(text) => self.text(&text),
+            Event::End(end) => self.end(&end),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl Output for VecDeque<Event<'static>> {
+    fn event(&mut self, event: Event, _: &ElementStyle) -> Result<(), Error> {
+        self.push_back(event.into_owned());
+        Ok(())
+    }
+}
+
+/// An element whose object is being read. Its start tag is written along
+/// with its first content, or as an empty element tag once the object ends
+/// without any.
+struct Element<'v> {
+    name: Cow<'v, str>,
+    /// The whole object of the element, or its attributes and style read so
+    /// far.
+    attributes: Cow<'v, Map<String, Value>>,
+    /// Whether the element has text, when known before its content is read.
+    has_text: Option<bool>,
+    started: bool,
+}
+
+impl Element<'_> {
+    fn start<O: Output>(&mut self, out: &mut O, has_text: bool) -> Result<(), Error> {
+        if self.started {
+            return Ok(());
+        }
+
+        let mut style = element_style(&self.attributes)?;
+        style.has_text = self.has_text.unwrap_or(has_text);
+        self.started = true;
+        out.event(
+            Event::Start(start_tag(&self.name, &self.attributes)?),
+            &style,
+        )
+    }
+
+    fn end<O: Output>(&self, out: &mut O) -> Result<(), Error> {
+        match self.started {
+            true => out.event(
+                Event::End(BytesEnd::borrowed(self.name.as_bytes())),
+                &ElementStyle::default(),
+            ),
+            false => out.event(
+                Event::Empty(start_tag(&self.name, &self.attributes)?),
+                &element_style(&self.attributes)?,
+            ),
+        }
+    }
+}
+
+/// An object or array open around what is read next.
+enum Frame<'v> {
+    /// The object of an element, or of the document itself when `None`.
+    Object(Option<Element<'v>>),
+    /// An array of elements, all named by the key that held it.
+    Array(Cow<'v, str>),
+}
+
+/// Turns the parts of a JSON value following the jxon conventions into
+/// events as they are read, keeping the objects and arrays open around them
+/// on an explicit stack rather than the native one so that deep values do
+/// not overflow it. Both `write_value` and `json_to_xml_writer` are driven
+/// by it, so they validate and write values the same way.
+pub(crate) struct Frames<'v> {
+    stack: Vec<Frame<'v>>,
+}
+
+impl<'v> Frames<'v> {
+    pub(crate) fn new() -> Self {
+        Frames { stack: vec![] }
+    }
+
+    /// The error for `value`, which does not belong where it was read.
+    pub(crate) fn invalid(&self, value: &Value) -> Error {
+        match self.stack.last() {
+            None => match value {
+                Value::Null => Error::JsonParseUnexpectedNull,
+                Value::Bool(_) => Error::JsonParseUnexpectedBool,
+                Value::Array(_) => Error::JsonParseUnexpectedArray,
+                _ => Error::JsonParseUnexpectedNumber,
+            },
+            Some(Frame::Object(_)) => Error::JsonParseExpectedArray,
+            Some(Frame::Array(_)) => Error::JsonParseExpectedObject,
+        }
+    }
+
+    /// The innermost open element, started with the content that follows.
+    fn content<O: Output>(&mut self, out: &mut O, has_text: bool) -> Result<(), Error> {
+        match self.stack.last_mut() {
+            Some(Frame::Object(Some(element))) => element.start(out, has_text),
+            _ => Ok(()),
+        }
+    }
+
+    /// Text, held by `key` in an object or making up the whole value.
+    pub(crate) fn text<O: Output>(
+        &mut self,
+        key: Option<&str>,
+        text: &str,
+        out: &mut O,
+    ) -> Result<(), Error> {
+        if let Some(Frame::Array(_)) = self.stack.last() {
+            return Err(Error::JsonParseExpectedObject);
+        }
+
+        let has_text = key.is_some_and(|key| key.len() == 1 && key.starts_with(TEXT_CHARACTER));
+        self.content(out, has_text)?;
+        out.event(
+            Event::Text(BytesText::from_plain_str(text)),
+            &ElementStyle::default(),
+        )
+    }
+
+    /// An attribute or style property of the innermost open element, which
+    /// must come before its content. The document has none, so they are
+    /// ignored there.
+    pub(crate) fn attribute(&mut self, key: String, value: Value) -> Result<(), Error> {
+        if let Some(Frame::Object(Some(element))) = self.stack.last_mut() {
+            if element.started {
+                return Err(Error::JsonParseAttributeAfterContent);
+            }
+
+            element.attributes.to_mut().insert(key, value);
+        }
+
+        Ok(())
+    }
+
+    /// The XML declaration, held by the `DECL_STRING` key of an object.
+    pub(crate) fn decl<O: Output>(&mut self, value: &Value, out: &mut O) -> Result<(), Error> {
+        self.content(out, false)?;
+        out.event(Event::Decl(read_decl(value)?), &ElementStyle::default())
+    }
+
+    /// The start of an array held by `key` in an object.
+    pub(crate) fn start_array<O: Output>(
+        &mut self,
+        key: Option<Cow<'v, str>>,
+        out: &mut O,
+    ) -> Result<(), Error> {
+        match (self.stack.last(), key) {
+            (Some(Frame::Object(_)), Some(key)) => {
+                self.content(out, false)?;
+                self.stack.push(Frame::Array(key));
+                Ok(())
+            }
+            _ => Err(self.invalid(&Value::Array(vec![]))),
+        }
+    }
+
+    pub(crate) fn end_array(&mut self) {
+        self.stack.pop();
+    }
+
+    /// The start of the object of the document or of an element. When the
+    /// whole object is known, the start tag is taken from it, and written
+    /// straight away if the element has content.
+    pub(crate) fn start_object<O: Output>(
+        &mut self,
+        object: Option<&'v Map<String, Value>>,
+        out: &mut O,
+    ) -> Result<(), Error> {
+        let mut element = match self.stack.last() {
+            None => None,
+            Some(Frame::Object(_)) => return Err(Error::JsonParseExpectedArray),
+            Some(Frame::Array(name)) => Some(Element {
+                name: name.clone(),
+                attributes: match object {
+                    Some(object) => Cow::Borrowed(object),
+                    None => Cow::Owned(Map::new()),
+                },
+                has_text: object.map(|object| object.contains_key(&TEXT_CHARACTER.to_string())),
+                started: false,
+            }),
+        };
+
+        if let (Some(element), Some(object)) = (&mut element, object) {
+            if object
+                .keys()
+                .any(|key| !matches!(entry(key), Entry::Attribute))
+            {
+                element.start(out, false)?;
+            }
+        }
+
+        self.stack.push(Frame::Object(element));
+        Ok(())
+    }
+
+    /// The end of the innermost object, which ends its element.
+    pub(crate) fn end_object<O: Output>(&mut self, out: &mut O) -> Result<(), Error> {
+        match self.stack.pop() {
+            Some(Frame::Object(Some(element))) => element.end(out),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// What is left to read of an object or array.
+enum Walk<'v> {
+    Object(serde_json::map::Iter<'v>),
+    Array(std::slice::Iter<'v, Value>),
+}
+
+/// Reads a value a part at a time into `Frames`, keeping the objects and
+/// arrays being read on an explicit stack.
+struct ValueWalk<'v> {
+    /// The value, until its first part is read.
+    value: Option<&'v Value>,
+    stack: Vec<Walk<'v>>,
+    frames: Frames<'v>,
+}
+
+impl<'v> ValueWalk<'v> {
+    fn new(value: &'v Value) -> Self {
+        ValueWalk {
+            value: Some(value),
+            stack: vec![],
+            frames: Frames::new(),
+        }
+    }
+
+    /// Read `value`, held by `key` in an object.
+    fn read<O: Output>(
+        &mut self,
+        key: Option<&'v str>,
+        value: &'v Value,
+        out: &mut O,
+    ) -> Result<(), Error> {
+        match value {
+            Value::String(text) => self.frames.text(key, text, out),
+            Value::Array(values) => {
+                self.frames.start_array(key.map(Cow::Borrowed), out)?;
+                self.stack.push(Walk::Array(values.iter()));
+                Ok(())
             }
+            Value::Object(object) => {
+                self.frames.start_object(Some(object), out)?;
+                self.stack.push(Walk::Object(object.iter()));
+                Ok(())
+            }
+            _ => Err(self.frames.invalid(value)),
+        }
+    }
+
+    /// Read the next part of the value, or return `false` once it was all
+    /// read.
+    fn step<O: Output>(&mut self, out: &mut O) -> Result<bool, Error> {
+        if let Some(value) = self.value.take() {
+            self.read(None, value, out)?;
+            return Ok(true);
+        }
+
+        match self.stack.last_mut() {
+            None => return Ok(false),
+            Some(Walk::Object(entries)) => match entries.next() {
+                None => {
+                    self.stack.pop();
+                    self.frames.end_object(out)?;
+                }
+                Some((key, value)) => match entry(key) {
+                    // The start tag is taken from the whole object.
+                    Entry::Attribute => {}
+                    Entry::Decl => self.frames.decl(value, out)?,
+                    Entry::Content => self.read(Some(key), value, out)?,
+                },
+            },
+            Some(Walk::Array(values)) => match values.next() {
+                None => {
+                    self.stack.pop();
+                    self.frames.end_array();
+                }
+                Some(value) => self.read(None, value, out)?,
+            },
         }
+
+        Ok(true)
     }
+}
 
+/// Write a value by formatting each of its events.
+fn write_value<W: Write>(writer: &mut Formatter<W>, value: &Value) -> Result<(), Error> {
+    let mut walk = ValueWalk::new(value);
+    while walk.step(writer)? {}
     Ok(())
 }
 
+/// Turn a JSON value following the jxon conventions into the quick-xml
+/// events that make up its XML, such as to write it with a quick-xml
+/// `Writer` in the middle of a larger document. This is what `json_to_xml`
+/// writes, before applying the `WriteOptions`.
+///
+/// Elements without content are `Event::Empty`, and the `"#empty"` and
+/// `"#quotes"` keys are left to the writer. The iterator ends after the
+/// first error.
+pub fn to_events(value: &Value) -> impl Iterator<Item = Result<Event<'static>, Error>> + '_ {
+    let mut walk = ValueWalk::new(value);
+    let mut events = VecDeque::new();
+    let mut finished = false;
+
+    std::iter::from_fn(move || loop {
+        if let Some(event) = events.pop_front() {
+            return Some(Ok(event));
+        }
+
+        if finished {
+            return None;
+        }
+
+        match walk.step(&mut events) {
+            Ok(more) => finished = !more,
+            Err(e) => {
+                finished = true;
+                return Some(Err(e));
+            }
+        }
+    })
+}
+
+/// Reads a value like its own `Deserialize` does, except that a key repeated
+/// in an object fails rather than replacing the earlier value. The text
+/// `xml_to_json_writer` writes in document order repeats keys for siblings
+/// that are not adjacent, so keeping only the last would lose elements.
+struct UniqueKeys;
+
+impl<'de> DeserializeSeed<'de> for UniqueKeys {
+    type Value = Value;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Value, D::Error> {
+        deserializer.deserialize_any(self)
+    }
+}
+
+impl<'de> Visitor<'de> for UniqueKeys {
+    type Value = Value;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a JSON value")
+    }
+
+    fn visit_unit<E: de::Error>(self) -> Result<Value, E> {
+        Ok(Value::Null)
+    }
+
+    fn visit_bool<E: de::Error>(self, value: bool) -> Result<Value, E> {
+        Ok(Value::from(value))
+    }
+
+    fn visit_i64<E: de::Error>(self, value: i64) -> Result<Value, E> {
+        Ok(Value::from(value))
+    }
+
+    fn visit_u64<E: de::Error>(self, value: u64) -> Result<Value, E> {
+        Ok(Value::from(value))
+    }
+
+    fn visit_f64<E: de::Error>(self, value: f64) -> Result<Value, E> {
+        Ok(Value::from(value))
+    }
+
+    fn visit_str<E: de::Error>(self, value: &str) -> Result<Value, E> {
+        Ok(Value::from(value))
+    }
+
+    fn visit_string<E: de::Error>(self, value: String) -> Result<Value, E> {
+        Ok(Value::from(value))
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Value, A::Error> {
+        let mut values = vec![];
+
+        while let Some(value) = seq.next_element_seed(UniqueKeys)? {
+            values.push(value);
+        }
+
+        Ok(Value::Array(values))
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Value, A::Error> {
+        let mut object = Map::new();
+
+        while let Some(key) = map.next_key::<String>()? {
+            if object.contains_key(&key) {
+                return Err(de::Error::custom(format_args!("duplicate key {:?}", key)));
+            }
+
+            let value = map.next_value_seed(UniqueKeys)?;
+            object.insert(key, value);
+        }
+
+        Ok(Value::Object(object))
+    }
+}
+
+/// Read a JSON string without serde_json's limit on nesting, extending the
+/// native stack on the heap as needed, and pass the value to `convert`. The
+/// value is then dropped without recursing, unlike serde_json's own drop.
+fn convert_json<T>(
+    json: &str,
+    convert: impl FnOnce(&Value) -> Result<T, Error>,
+) -> Result<T, Error> {
+    let mut deserializer = serde_json::Deserializer::from_str(json);
+    deserializer.disable_recursion_limit();
+    let value = UniqueKeys
+        .deserialize(serde_stacker::Deserializer::new(&mut deserializer))
+        .map_err(Error::JsonParseError)?;
+    let result = deserializer
+        .end()
+        .map_err(Error::JsonParseError)
+        .and_then(|_| convert(&value));
+
+    drop_value(value);
+    result
+}
+
+/// Drop a value keeping what is left to drop on an explicit stack, so that
+/// deep values do not overflow the native one.
+fn drop_value(value: Value) {
+    let mut values = vec![value];
+
+    while let Some(value) = values.pop() {
+        match value {
+            Value::Array(array) => values.extend(array),
+            Value::Object(object) => values.extend(object.into_iter().map(|(_, value)| value)),
+            _ => {}
+        }
+    }
+}
+
 /// Convert a JSON string to an XML string.
 /// The JSON provided must be compatible with the conventions used by the jxon crate.
-pub fn json_to_xml(json: &str, indent: Option<(u8, usize)>) -> Result<String, Error> {
-    let mut writer = match indent {
-        None => Writer::new(Cursor::new(Vec::new())),
-        Some((c, size)) => Writer::new_with_indent(Cursor::new(vec![]), c, size),
-    };
-    write_value(
-        &mut writer,
-        serde_json::from_str(json).map_err(|e| Error::JsonParseError(e))?,
-    )?;
-    bytes_to_string(&writer.into_inner().into_inner())
+/// A key repeated in an object fails with `Error::JsonParseError`, since only
+/// one of its values could be written.
+pub fn json_to_xml(json: &str, options: &WriteOptions) -> Result<String, Error> {
+    convert_json(json, |value| value_to_xml(value, options))
+}
+
+/// Convert a JSON string to XML bytes in the encoding named by its
+/// declaration, or UTF-8 if it has none.
+/// Characters that the encoding cannot represent are written as character
+/// references.
+pub fn json_to_xml_bytes(json: &str, options: &WriteOptions) -> Result<Vec<u8>, Error> {
+    convert_json(json, |value| {
+        let label = match value.get(DECL_STRING).and_then(|decl| decl.get("encoding")) {
+            Some(encoding) => encoding.as_str().ok_or(Error::JsonParseInvalidDecl)?,
+            None => "UTF-8",
+        };
+
+        encode(&value_to_xml(value, options)?, label)
+    })
+}
+
+pub(crate) fn value_to_xml(value: &Value, options: &WriteOptions) -> Result<String, Error> {
+    let mut writer = Formatter::new(Vec::new(), options);
+    write_value(&mut writer, value)?;
+    bytes_to_string(&writer.finish()?)
 }
@@ -0,0 +1,176 @@
+use crate::{
+    error::Error,
+    format::Formatter,
+    to_xml::{entry, Entry, Frames},
+    *,
+};
+use serde::de::{self, DeserializeSeed, Deserializer, MapAccess, SeqAccess, Visitor};
+use serde_json::Value;
+use std::{
+    borrow::Cow,
+    fmt,
+    io::{BufRead, Write},
+};
+
+/// Writes XML while JSON is deserialized, keeping the first error from the
+/// jxon conventions, which serde only sees as a custom error.
+struct Stream<'o, W: Write> {
+    writer: Formatter<'o, W>,
+    frames: Frames<'static>,
+    error: Option<Error>,
+}
+
+impl<W: Write> Stream<'_, W> {
+    fn check<E: de::Error>(&mut self, result: Result<(), Error>) -> Result<(), E> {
+        result.map_err(|error| {
+            let custom = E::custom(&error);
+            self.error = Some(error);
+            custom
+        })
+    }
+
+    fn invalid<E: de::Error>(&mut self, value: Value) -> Result<(), E> {
+        let error = self.frames.invalid(&value);
+        self.check(Err(error))
+    }
+}
+
+/// A value held by `key` in an object, or making up the whole document
+/// or an element of an array when it is `None`.
+struct ValueSeed<'s, 'o, W: Write> {
+    stream: &'s mut Stream<'o, W>,
+    key: Option<String>,
+}
+
+impl<'de, W: Write> DeserializeSeed<'de> for ValueSeed<'_, '_, W> {
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        deserializer.deserialize_any(self)
+    }
+}
+
+impl<'de, W: Write> Visitor<'de> for ValueSeed<'_, '_, W> {
+    type Value = ();
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a jxon value")
+    }
+
+    fn visit_unit<E: de::Error>(self) -> Result<(), E> {
+        self.stream.invalid(Value::Null)
+    }
+
+    fn visit_bool<E: de::Error>(self, value: bool) -> Result<(), E> {
+        self.stream.invalid(Value::from(value))
+    }
+
+    fn visit_i64<E: de::Error>(self, value: i64) -> Result<(), E> {
+        self.stream.invalid(Value::from(value))
+    }
+
+    fn visit_u64<E: de::Error>(self, value: u64) -> Result<(), E> {
+        self.stream.invalid(Value::from(value))
+    }
+
+    fn visit_f64<E: de::Error>(self, value: f64) -> Result<(), E> {
+        self.stream.invalid(Value::from(value))
+    }
+
+    fn visit_str<E: de::Error>(self, text: &str) -> Result<(), E> {
+        let stream = self.stream;
+        let result = stream
+            .frames
+            .text(self.key.as_deref(), text, &mut stream.writer);
+        stream.check(result)
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<(), A::Error> {
+        let stream = self.stream;
+        let result = stream
+            .frames
+            .start_array(self.key.map(Cow::Owned), &mut stream.writer);
+        stream.check(result)?;
+
+        while let Some(()) = seq.next_element_seed(ValueSeed {
+            stream: &mut *stream,
+            key: None,
+        })? {}
+
+        stream.frames.end_array();
+        Ok(())
+    }
+
+    /// Write the entries of an object as they are read, in the same way as
+    /// `write_value`.
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<(), A::Error> {
+        let stream = self.stream;
+        let result = stream.frames.start_object(None, &mut stream.writer);
+        stream.check(result)?;
+
+        while let Some(key) = map.next_key::<String>()? {
+            match entry(&key) {
+                Entry::Attribute => {
+                    let value = map.next_value()?;
+                    let result = stream.frames.attribute(key, value);
+                    stream.check(result)?;
+                }
+                Entry::Decl => {
+                    let decl = map.next_value::<Value>()?;
+                    let result = stream.frames.decl(&decl, &mut stream.writer);
+                    stream.check(result)?;
+                }
+                Entry::Content => map.next_value_seed(ValueSeed {
+                    stream: &mut *stream,
+                    key: Some(key),
+                })?,
+            }
+        }
+
+        let result = stream.frames.end_object(&mut stream.writer);
+        stream.check(result)
+    }
+}
+
+/// Convert JSON read from `reader` to XML written to `writer`, for inputs
+/// too large to deserialize into a value first. The JSON must follow the
+/// conventions used by the jxon crate.
+///
+/// The XML is written as the JSON is read, so memory use depends on how
+/// deeply the elements are nested rather than on the size of the input. For
+/// that, the attributes and style properties of an element must come before
+/// its children and text, as the jxon crate writes them; one coming after
+/// them fails with `Error::JsonParseAttributeAfterContent`. Likewise, when
+/// indenting, the `"_"` text of an element must come before its children,
+/// as `xml_to_json` writes it, or it fails with
+/// `Error::JsonParseTextAfterIndentedContent` rather than adding whitespace
+/// to the text. Otherwise the XML and errors are those of `json_to_xml`.
+///
+/// Unlike serde_json by default, this does not limit how deeply the input
+/// is nested; the native stack is extended on the heap as needed instead.
+pub fn json_to_xml_writer<B: BufRead, W: Write>(
+    reader: B,
+    writer: W,
+    options: &WriteOptions,
+) -> Result<(), Error> {
+    let mut deserializer = serde_json::Deserializer::from_reader(reader);
+    deserializer.disable_recursion_limit();
+    let mut stream = Stream {
+        writer: Formatter::new(writer, options),
+        frames: Frames::new(),
+        error: None,
+    };
+    let result = ValueSeed {
+        stream: &mut stream,
+        key: None,
+    }
+    .deserialize(serde_stacker::Deserializer::new(&mut deserializer))
+    .and_then(|_| deserializer.end());
+
+    if let Some(error) = stream.error {
+        return Err(error);
+    }
+
+    result.map_err(Error::JsonParseError)?;
+    stream.writer.finish()?.flush().map_err(Error::IoError)
+}
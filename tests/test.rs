@@ -14,7 +14,7 @@ fn check_different_xml(xml: &str, xml_transformed: &str, expected_json: Value) {
         "converting xml to json"
     );
     assert_eq!(
-        json_to_xml(&json_value, None).expect("json to xml"),
+        json_to_xml(&json_value, &WriteOptions::default()).expect("json to xml"),
         xml_transformed,
         "converting json to xml"
     );
@@ -301,5 +301,2057 @@ fn serde() {
     };
 
     assert_eq!(deserialize::<Test>(xml).unwrap(), test);
-    assert_eq!(serialize(test, None).unwrap(), xml);
+    assert_eq!(serialize(test, &WriteOptions::default()).unwrap(), xml);
+}
+
+#[test]
+fn attribute_whitespace() {
+    check(
+        r#"<root Something="a&#9;b&#10;c&#13;d"/>"#,
+        json!({
+            "root": [
+                {
+                    "$Something": "a\tb\nc\rd"
+                }
+            ]
+        }),
+    );
+
+    let xml = "<root Something=\"a\tb\r\nc&#10;d\"/>";
+
+    assert_eq!(
+        xml_to_json(xml).unwrap(),
+        json!({
+            "root": [
+                {
+                    "$Something": "a\tb\r\nc\nd"
+                }
+            ]
+        })
+    );
+
+    assert_eq!(
+        xml_to_json_with_options(
+            xml,
+            &ReadOptions {
+                normalize_attribute_values: true,
+                ..Default::default()
+            }
+        )
+        .unwrap(),
+        json!({
+            "root": [
+                {
+                    "$Something": "a b c\nd"
+                }
+            ]
+        })
+    );
+}
+
+#[test]
+fn write_options() {
+    let json = json!({
+        "#": {
+            "version": "1.0"
+        },
+        "root": [{
+            "$a": "1",
+            "$b": "it's",
+            "item": [
+                {
+                    "_": "text"
+                },
+                {
+                    "_": "mixed",
+                    "child": [{}]
+                },
+                {
+                    "child": [{}]
+                }
+            ],
+            "empty": [{}]
+        }]
+    })
+    .to_string();
+
+    assert_eq!(
+        json_to_xml(
+            &json,
+            &WriteOptions {
+                indent: Some(Indent {
+                    character: b' ',
+                    size: 2
+                }),
+                ..Default::default()
+            }
+        )
+        .unwrap(),
+        r#"<?xml version="1.0"?>
+<root a="1" b="it&apos;s">
+  <item>text</item>
+  <item>mixed<child/></item>
+  <item>
+    <child/>
+  </item>
+  <empty/>
+</root>"#
+    );
+
+    assert_eq!(
+        json_to_xml(
+            &json,
+            &WriteOptions {
+                indent: Some(Indent {
+                    character: b'\t',
+                    size: 1
+                }),
+                newline: Newline::CrLf,
+                self_close_empty: false,
+                quote: QuoteStyle::Single,
+                attributes_per_line: Some(1),
+                trailing_newline: true,
+                ..Default::default()
+            }
+        )
+        .unwrap(),
+        "<?xml version=\"1.0\"?>\r\n<root\r\n\ta='1'\r\n\tb='it&apos;s'>\r\n\t<item>text</item>\r\n\t\
+         <item>mixed<child></child></item>\r\n\t<item>\r\n\t\t<child></child>\r\n\t</item>\r\n\t\
+         <empty></empty>\r\n</root>\r\n"
+    );
+
+    assert_eq!(
+        json_to_xml(
+            &json,
+            &WriteOptions {
+                space_before_self_close: true,
+                ..Default::default()
+            }
+        )
+        .unwrap(),
+        r#"<?xml version="1.0"?><root a="1" b="it&apos;s"><item>text</item><item>mixed<child /></item><item><child /></item><empty /></root>"#
+    );
+}
+
+#[test]
+fn preserve_style() {
+    let options = ReadOptions {
+        preserve_style: true,
+        ..Default::default()
+    };
+    let xml = r#"<root a='1' b="2"><expanded></expanded><closed c='3'/><text>x</text></root>"#;
+    let json = xml_to_json_with_options(xml, &options).unwrap();
+
+    assert_eq!(
+        json,
+        json!({
+            "root": [{
+                "expanded": [{
+                    "#empty": "expanded"
+                }],
+                "closed": [{
+                    "$c": "3",
+                    "#quotes": "'",
+                    "#empty": "self-closing"
+                }],
+                "text": [{
+                    "_": "x"
+                }],
+                "$a": "1",
+                "$b": "2",
+                "#quotes": "'\""
+            }]
+        })
+    );
+
+    assert_eq!(
+        json_to_xml(&json.to_string(), &WriteOptions::default()).unwrap(),
+        xml
+    );
+
+    assert_eq!(
+        json_to_xml(
+            &json.to_string(),
+            &WriteOptions {
+                self_close_empty: false,
+                ..Default::default()
+            }
+        )
+        .unwrap(),
+        xml
+    );
+
+    assert_eq!(
+        xml_to_json(xml).unwrap(),
+        json!({
+            "root": [{
+                "expanded": [{}],
+                "closed": [{
+                    "$c": "3"
+                }],
+                "text": [{
+                    "_": "x"
+                }],
+                "$a": "1",
+                "$b": "2"
+            }]
+        })
+    );
+
+    assert!(json_to_xml(
+        &json!({ "root": [{ "#empty": "open" }] }).to_string(),
+        &WriteOptions::default()
+    )
+    .is_err());
+}
+
+#[test]
+fn whitespace() {
+    let xml = "<root><a>  x \n y  </a><b> </b><c xml:space=\"preserve\"> <d> </d><e xml:space=\"default\"> </e></c></root>";
+    let read = |whitespace| {
+        xml_to_json_with_options(
+            xml,
+            &ReadOptions {
+                whitespace,
+                ..Default::default()
+            },
+        )
+        .unwrap()
+    };
+
+    assert_eq!(
+        read(Whitespace::Preserve),
+        json!({
+            "root": [{
+                "a": [{ "_": "  x \n y  " }],
+                "b": [{ "_": " " }],
+                "c": [{
+                    "_": " ",
+                    "d": [{ "_": " " }],
+                    "e": [{ "_": " ", "$xml:space": "default" }],
+                    "$xml:space": "preserve"
+                }]
+            }]
+        })
+    );
+
+    assert_eq!(
+        read(Whitespace::DropWhitespaceOnly),
+        xml_to_json(xml).unwrap()
+    );
+    assert_eq!(
+        read(Whitespace::DropWhitespaceOnly),
+        json!({
+            "root": [{
+                "a": [{ "_": "  x \n y  " }],
+                "b": [{}],
+                "c": [{
+                    "_": " ",
+                    "d": [{ "_": " " }],
+                    "e": [{ "$xml:space": "default" }],
+                    "$xml:space": "preserve"
+                }]
+            }]
+        })
+    );
+
+    assert_eq!(
+        read(Whitespace::Trim)["root"][0]["a"],
+        json!([{ "_": "x \n y" }])
+    );
+
+    assert_eq!(
+        read(Whitespace::Collapse)["root"][0]["a"],
+        json!([{ "_": "x y" }])
+    );
+
+    assert_eq!(
+        read(Whitespace::Collapse)["root"][0]["c"][0]["d"],
+        json!([{ "_": " " }])
+    );
+
+    assert_eq!(
+        xml_to_json_with_options(
+            "<a>hello<b/> </a>",
+            &ReadOptions {
+                whitespace: Whitespace::Preserve,
+                ..Default::default()
+            },
+        )
+        .unwrap(),
+        json!({ "a": [{ "_": "hello ", "b": [{}] }] })
+    );
+}
+
+#[test]
+fn c14n_spec_pis_comments_and_outside_of_document_element() {
+    // Canonical XML 1.0, section 3.1
+    let xml = r#"<?xml version="1.0"?>
+
+<?xml-stylesheet   href="doc.xsl"
+   type="text/xsl"   ?>
+
+<!DOCTYPE doc SYSTEM "doc.dtd">
+
+<doc>Hello, world!<!-- Comment 1 --></doc>
+
+<?pi-without-data     ?>
+
+<!-- Comment 2 -->
+
+<!-- Comment 3 -->"#;
+
+    assert_eq!(
+        canonicalize_xml(xml, &CanonicalOptions::default()).unwrap(),
+        r#"<?xml-stylesheet href="doc.xsl"
+   type="text/xsl"   ?>
+<doc>Hello, world!</doc>
+<?pi-without-data?>"#
+    );
+
+    assert_eq!(
+        canonicalize_xml(
+            xml,
+            &CanonicalOptions {
+                with_comments: true,
+                ..Default::default()
+            }
+        )
+        .unwrap(),
+        r#"<?xml-stylesheet href="doc.xsl"
+   type="text/xsl"   ?>
+<doc>Hello, world!<!-- Comment 1 --></doc>
+<?pi-without-data?>
+<!-- Comment 2 -->
+<!-- Comment 3 -->"#
+    );
+}
+
+#[test]
+fn c14n_spec_whitespace_in_document_content() {
+    // Canonical XML 1.0, section 3.2
+    let xml = r#"<doc>
+   <clean>   </clean>
+   <dirty>   A   B   </dirty>
+   <mixed>
+      A
+      <clean>   </clean>
+      B
+      <dirty>   A   B   </dirty>
+      C
+   </mixed>
+</doc>"#;
+
+    assert_eq!(
+        canonicalize_xml(xml, &CanonicalOptions::default()).unwrap(),
+        xml
+    );
+}
+
+#[test]
+fn c14n_spec_start_and_end_tags() {
+    // Canonical XML 1.0, section 3.3, without the DTD that adds a default
+    // attribute to e9.
+    let xml = r#"<doc>
+   <e1   />
+   <e2   ></e2>
+   <e3   name = "elem3"   id="elem3"   />
+   <e4   name="elem4"   id="elem4"   ></e4>
+   <e5 a:attr="out" b:attr="sorted" attr2="all" attr="I'm"
+      xmlns:b="http://www.ietf.org"
+      xmlns:a="http://www.w3.org"
+      xmlns="http://example.org"/>
+   <e6 xmlns="" xmlns:a="http://www.w3.org">
+      <e7 xmlns="http://www.ietf.org">
+         <e8 xmlns="" xmlns:a="http://www.w3.org">
+            <e9 xmlns="" xmlns:a="http://www.ietf.org"/>
+         </e8>
+      </e7>
+   </e6>
+</doc>"#;
+
+    assert_eq!(
+        canonicalize_xml(xml, &CanonicalOptions::default()).unwrap(),
+        r#"<doc>
+   <e1></e1>
+   <e2></e2>
+   <e3 id="elem3" name="elem3"></e3>
+   <e4 id="elem4" name="elem4"></e4>
+   <e5 xmlns="http://example.org" xmlns:a="http://www.w3.org" xmlns:b="http://www.ietf.org" attr="I'm" attr2="all" b:attr="sorted" a:attr="out"></e5>
+   <e6 xmlns:a="http://www.w3.org">
+      <e7 xmlns="http://www.ietf.org">
+         <e8 xmlns="">
+            <e9 xmlns:a="http://www.ietf.org"></e9>
+         </e8>
+      </e7>
+   </e6>
+</doc>"#
+    );
+}
+
+#[test]
+fn c14n_spec_character_modifications_and_references() {
+    // Canonical XML 1.0, section 3.4, without the elements whose output
+    // depends on attribute types declared in the DTD.
+    let xml = r#"<doc>
+   <text>First line&#x0d;&#10;Second line</text>
+   <value>&#x32;</value>
+   <compute><![CDATA[value>"0" && value<"10" ?"valid":"error"]]></compute>
+   <compute expr='value>"0" &amp;&amp; value&lt;"10" ?"valid":"error"'>valid</compute>
+   <norm attr=' &apos;   &#x20;&#13;&#xa;&#9;   &apos; '/>
+</doc>"#;
+
+    assert_eq!(
+        canonicalize_xml(xml, &CanonicalOptions::default()).unwrap(),
+        r#"<doc>
+   <text>First line&#xD;
+Second line</text>
+   <value>2</value>
+   <compute>value&gt;"0" &amp;&amp; value&lt;"10" ?"valid":"error"</compute>
+   <compute expr="value>&quot;0&quot; &amp;&amp; value&lt;&quot;10&quot; ?&quot;valid&quot;:&quot;error&quot;">valid</compute>
+   <norm attr=" '    &#xD;&#xA;&#x9;   ' "></norm>
+</doc>"#
+    );
+}
+
+#[test]
+fn c14n_spec_utf8_encoding() {
+    // Canonical XML 1.0, section 3.6
+    assert_eq!(
+        canonicalize_xml(
+            r#"<?xml version="1.0" encoding="ISO-8859-1"?>
+<doc>&#169;</doc>"#,
+            &CanonicalOptions::default()
+        )
+        .unwrap(),
+        "<doc>\u{a9}</doc>"
+    );
+}
+
+#[test]
+fn c14n_exclusive() {
+    let xml = r#"<n0:local xmlns:n0="foo:bar" xmlns:n3="ftp://example.org">
+  <n1:elem2 xmlns:n1="http://example.net" xml:lang="en">
+    <n3:stuff xmlns:n3="ftp://example.org"/>
+  </n1:elem2>
+</n0:local>"#;
+
+    assert_eq!(
+        canonicalize_xml(xml, &CanonicalOptions::default()).unwrap(),
+        r#"<n0:local xmlns:n0="foo:bar" xmlns:n3="ftp://example.org">
+  <n1:elem2 xmlns:n1="http://example.net" xml:lang="en">
+    <n3:stuff></n3:stuff>
+  </n1:elem2>
+</n0:local>"#
+    );
+
+    assert_eq!(
+        canonicalize_xml(
+            xml,
+            &CanonicalOptions {
+                exclusive: true,
+                ..Default::default()
+            }
+        )
+        .unwrap(),
+        r#"<n0:local xmlns:n0="foo:bar">
+  <n1:elem2 xmlns:n1="http://example.net" xml:lang="en">
+    <n3:stuff xmlns:n3="ftp://example.org"></n3:stuff>
+  </n1:elem2>
+</n0:local>"#
+    );
+
+    assert_eq!(
+        canonicalize_xml(
+            xml,
+            &CanonicalOptions {
+                exclusive: true,
+                inclusive_prefixes: vec!["n3".to_owned()],
+                ..Default::default()
+            }
+        )
+        .unwrap(),
+        r#"<n0:local xmlns:n0="foo:bar" xmlns:n3="ftp://example.org">
+  <n1:elem2 xmlns:n1="http://example.net" xml:lang="en">
+    <n3:stuff></n3:stuff>
+  </n1:elem2>
+</n0:local>"#
+    );
+
+    assert_eq!(
+        canonicalize_xml(
+            r#"<a xmlns="urn:a"><b xmlns=""><c/></b></a>"#,
+            &CanonicalOptions {
+                exclusive: true,
+                ..Default::default()
+            }
+        )
+        .unwrap(),
+        r#"<a xmlns="urn:a"><b xmlns=""><c></c></b></a>"#
+    );
+}
+
+#[test]
+fn c14n_json() {
+    assert_eq!(
+        canonicalize_json(
+            &json!({
+                "#": {
+                    "version": "1.0"
+                },
+                "root": [{
+                    "$z": "1",
+                    "$a": "a\tb",
+                    "empty": [{}],
+                    "_": "x > y"
+                }]
+            }),
+            &CanonicalOptions::default()
+        )
+        .unwrap(),
+        r#"<root a="a&#x9;b" z="1"><empty></empty>x &gt; y</root>"#
+    );
+}
+
+#[test]
+fn encodings() {
+    let mut xml = br#"<?xml version="1.0" encoding="Shift_JIS"?><root name=""#.to_vec();
+    xml.extend_from_slice(&[0x93, 0xfa, 0x96, 0x7b]);
+    xml.extend_from_slice(b"\"/>");
+
+    assert_eq!(
+        xml_bytes_to_json(&xml).unwrap(),
+        json!({
+            "#": {
+                "version": "1.0",
+                "encoding": "Shift_JIS"
+            },
+            "root": [{
+                "$name": "\u{65e5}\u{672c}"
+            }]
+        })
+    );
+
+    assert_eq!(
+        xml_bytes_to_json(b"<?xml version='1.0' encoding='ISO-8859-1'?><root>caf\xe9</root>")
+            .unwrap(),
+        json!({
+            "#": {
+                "version": "1.0",
+                "encoding": "ISO-8859-1"
+            },
+            "root": [{
+                "_": "caf\u{e9}"
+            }]
+        })
+    );
+
+    let utf16: Vec<u8> = "\u{feff}<root>\u{e9}</root>"
+        .encode_utf16()
+        .flat_map(|u| u.to_le_bytes().to_vec())
+        .collect();
+
+    assert_eq!(
+        xml_bytes_to_json(&utf16).unwrap(),
+        json!({
+            "#": {
+                "version": "1.0",
+                "encoding": "UTF-16LE"
+            },
+            "root": [{
+                "_": "\u{e9}"
+            }]
+        })
+    );
+
+    assert_eq!(
+        xml_bytes_to_json(b"\xef\xbb\xbf<root/>").unwrap(),
+        json!({
+            "root": [{}]
+        })
+    );
+
+    assert!(matches!(
+        xml_bytes_to_json(b"<?xml version=\"1.0\" encoding=\"x-unknown\"?><root/>"),
+        Err(Error::XmlUnsupportedEncoding(_))
+    ));
+
+    assert!(matches!(
+        xml_bytes_to_json(b"<root>\xff</root>"),
+        Err(Error::XmlMalformedEncoding("UTF-8"))
+    ));
+
+    // ISO-8859-1 and US-ASCII are read as themselves rather than as
+    // windows-1252, which encoding_rs gives for their labels.
+    assert_eq!(
+        xml_bytes_to_json(b"<?xml version='1.0' encoding='latin1'?><root>\x80\xe9</root>").unwrap()
+            ["root"][0]["_"],
+        "\u{80}\u{e9}"
+    );
+    assert!(matches!(
+        xml_bytes_to_json(b"<?xml version='1.0' encoding='US-ASCII'?><root>\xe9</root>"),
+        Err(Error::XmlMalformedEncoding("US-ASCII"))
+    ));
+    assert_eq!(
+        xml_bytes_to_json(b"<?xml version='1.0' encoding='windows-1252'?><root>\x80</root>")
+            .unwrap()["root"][0]["_"],
+        "\u{20ac}"
+    );
+}
+
+#[test]
+fn encode_output() {
+    let json = json!({
+        "#": {
+            "version": "1.0",
+            "encoding": "ISO-8859-1"
+        },
+        "root": [{
+            "$name": "caf\u{e9} \u{263a}",
+            "_": "\u{e9}\u{65e5}"
+        }]
+    })
+    .to_string();
+
+    assert_eq!(
+        json_to_xml_bytes(&json, &WriteOptions::default()).unwrap(),
+        b"<?xml version=\"1.0\" encoding=\"ISO-8859-1\"?><root name=\"caf\xe9 &#9786;\">\xe9&#26085;</root>".to_vec()
+    );
+
+    // Unlike windows-1252, which shares its labels in encoding_rs, neither
+    // ISO-8859-1 nor US-ASCII has the euro sign.
+    for (encoding, expected) in [
+        ("latin1", &b"\xe9&#8364;"[..]),
+        ("US-ASCII", &b"&#233;&#8364;"[..]),
+    ] {
+        let json = json!({
+            "#": { "version": "1.0", "encoding": encoding },
+            "root": [{ "_": "\u{e9}\u{20ac}" }]
+        })
+        .to_string();
+        let bytes = json_to_xml_bytes(&json, &WriteOptions::default()).unwrap();
+
+        assert!(bytes.ends_with(&[b"<root>", expected, b"</root>"].concat()));
+    }
+
+    let json = json!({
+        "#": {
+            "version": "1.0",
+            "encoding": "Shift_JIS"
+        },
+        "root": [{
+            "_": "\u{65e5}\u{672c}"
+        }]
+    })
+    .to_string();
+    let bytes = json_to_xml_bytes(&json, &WriteOptions::default()).unwrap();
+
+    assert!(bytes.ends_with(b"<root>\x93\xfa\x96\x7b</root>"));
+    assert_eq!(xml_bytes_to_json(&bytes).unwrap().to_string(), json);
+
+    let json = json!({
+        "#": {
+            "version": "1.0",
+            "encoding": "UTF-16"
+        },
+        "root": [{}]
+    })
+    .to_string();
+    let bytes = json_to_xml_bytes(&json, &WriteOptions::default()).unwrap();
+
+    assert!(bytes.starts_with(&[0xff, 0xfe, b'<', 0]));
+    assert_eq!(xml_bytes_to_json(&bytes).unwrap().to_string(), json);
+
+    assert_eq!(
+        json_to_xml_bytes(
+            &json!({ "root": [{ "_": "\u{263a}" }] }).to_string(),
+            &WriteOptions::default()
+        )
+        .unwrap(),
+        "<root>\u{263a}</root>".as_bytes()
+    );
+
+    assert!(matches!(
+        json_to_xml_bytes(
+            &json!({
+                "#": { "version": "1.0", "encoding": "x-unknown" },
+                "root": [{}]
+            })
+            .to_string(),
+            &WriteOptions::default()
+        ),
+        Err(Error::JsonParseUnsupportedEncoding(_))
+    ));
+
+    assert!(matches!(
+        json_to_xml_bytes(
+            &json!({
+                "#": { "version": "1.0", "encoding": "ISO-8859-1" },
+                "r\u{263a}": [{}]
+            })
+            .to_string(),
+            &WriteOptions::default()
+        ),
+        Err(Error::JsonParseUnencodableName('\u{263a}'))
+    ));
+}
+
+#[test]
+fn limits() {
+    let read = |xml: &str, limits: ParseLimits| {
+        xml_to_json_with_options(
+            xml,
+            &ReadOptions {
+                limits,
+                ..Default::default()
+            },
+        )
+    };
+    let xml = r#"<root a="1" b="2"><child>text</child><child/></root>"#;
+
+    assert!(read(
+        xml,
+        ParseLimits {
+            max_depth: Some(2),
+            max_bytes: Some(xml.len()),
+            max_attributes: Some(2),
+            max_text_length: Some(4),
+            max_elements: Some(3),
+            ..Default::default()
+        }
+    )
+    .is_ok());
+
+    assert!(matches!(
+        read(
+            xml,
+            ParseLimits {
+                max_depth: Some(1),
+                ..Default::default()
+            }
+        ),
+        Err(Error::XmlParseDepthLimitExceeded)
+    ));
+
+    assert!(matches!(
+        read(
+            xml,
+            ParseLimits {
+                max_bytes: Some(xml.len() - 1),
+                ..Default::default()
+            }
+        ),
+        Err(Error::XmlParseSizeLimitExceeded)
+    ));
+
+    assert!(matches!(
+        read(
+            xml,
+            ParseLimits {
+                max_attributes: Some(1),
+                ..Default::default()
+            }
+        ),
+        Err(Error::XmlParseAttributeLimitExceeded)
+    ));
+
+    assert!(matches!(
+        read(
+            xml,
+            ParseLimits {
+                max_text_length: Some(3),
+                ..Default::default()
+            }
+        ),
+        Err(Error::XmlParseTextLimitExceeded)
+    ));
+
+    assert!(matches!(
+        read(
+            xml,
+            ParseLimits {
+                max_elements: Some(2),
+                ..Default::default()
+            }
+        ),
+        Err(Error::XmlParseElementLimitExceeded)
+    ));
+
+    let deep = format!("{}{}", "<a>".repeat(100_000), "</a>".repeat(100_000));
+
+    assert!(matches!(
+        read(
+            &deep,
+            ParseLimits {
+                max_depth: Some(64),
+                ..Default::default()
+            }
+        ),
+        Err(Error::XmlParseDepthLimitExceeded)
+    ));
+}
+
+/// Drop a value without recursing, since serde_json's own drop recurses and
+/// would overflow the stack of a deep value.
+fn drop_deep(value: Value) {
+    let mut values = vec![value];
+
+    while let Some(value) = values.pop() {
+        match value {
+            Value::Array(array) => values.extend(array),
+            Value::Object(object) => values.extend(object.into_iter().map(|(_, value)| value)),
+            _ => {}
+        }
+    }
+}
+
+#[test]
+fn deep_documents() {
+    std::thread::Builder::new()
+        .stack_size(256 * 1024)
+        .spawn(|| {
+            let depth = 100_000;
+            let xml = format!("{}{}", "<a>".repeat(depth), "</a>".repeat(depth));
+            let json = xml_to_json(&xml).unwrap();
+
+            assert_eq!(
+                canonicalize_json(&json, &CanonicalOptions::default()).unwrap(),
+                xml
+            );
+
+            drop_deep(json);
+
+            let json = format!("{}{{}}{}", r#"{"a":["#.repeat(depth), "]}".repeat(depth));
+            let xml = format!(
+                "{}<a/>{}",
+                "<a>".repeat(depth - 1),
+                "</a>".repeat(depth - 1)
+            );
+            let options = WriteOptions::default();
+            assert_eq!(json_to_xml(&json, &options).unwrap(), xml);
+
+            let mut out = vec![];
+            json_to_xml_writer(json.as_bytes(), &mut out, &options).unwrap();
+            assert_eq!(String::from_utf8(out).unwrap(), xml);
+
+            let document = Document::parse(&xml).unwrap();
+            assert_eq!(document.to_xml(&options).unwrap(), xml);
+            drop(document);
+        })
+        .unwrap()
+        .join()
+        .unwrap();
+}
+
+#[test]
+fn entities() {
+    let mut custom = std::collections::HashMap::new();
+    custom.insert("nbsp".to_owned(), "\u{a0}".to_owned());
+    custom.insert("copy".to_owned(), "\u{a9}".to_owned());
+    let options = ReadOptions {
+        entities: custom,
+        ..Default::default()
+    };
+
+    assert_eq!(
+        xml_to_json_with_options(r#"<root a="&copy;">1&nbsp;2 &amp; &#x33;</root>"#, &options)
+            .unwrap(),
+        json!({
+            "root": [{
+                "_": "1\u{a0}2 & 3",
+                "$a": "\u{a9}"
+            }]
+        })
+    );
+
+    assert!(matches!(
+        xml_to_json("<root>&nbsp;</root>"),
+        Err(Error::XmlParseUnknownEntity(ref name)) if name == "nbsp"
+    ));
+
+    let xml = r#"<!DOCTYPE root [
+  <!-- <!ENTITY commented "no"> -->
+  <!ENTITY % parameter "ignored">
+  <!ENTITY external SYSTEM "external.txt">
+  <!ENTITY name "jxon">
+  <!ENTITY greeting 'Hello, &name;&#33;'>
+  <!ENTITY name "ignored">
+  <!ENTITY escaped "&#38;amp;">
+]>
+<root who="&name;">&greeting; &escaped;</root>"#;
+
+    assert_eq!(
+        xml_to_json(xml).unwrap(),
+        json!({
+            "root": [{
+                "_": "Hello, jxon! &",
+                "$who": "jxon"
+            }]
+        })
+    );
+
+    assert!(matches!(
+        xml_to_json(r#"<!DOCTYPE root [<!ENTITY a "x&b;"><!ENTITY b "&a;">]><root>&a;</root>"#),
+        Err(Error::XmlParseRecursiveEntity(ref name)) if name == "a"
+    ));
+
+    let laughs = r#"<!DOCTYPE lolz [
+  <!ENTITY lol "lol">
+  <!ENTITY lol1 "&lol;&lol;&lol;&lol;&lol;&lol;&lol;&lol;&lol;&lol;">
+  <!ENTITY lol2 "&lol1;&lol1;&lol1;&lol1;&lol1;&lol1;&lol1;&lol1;&lol1;&lol1;">
+  <!ENTITY lol3 "&lol2;&lol2;&lol2;&lol2;&lol2;&lol2;&lol2;&lol2;&lol2;&lol2;">
+  <!ENTITY lol4 "&lol3;&lol3;&lol3;&lol3;&lol3;&lol3;&lol3;&lol3;&lol3;&lol3;">
+  <!ENTITY lol5 "&lol4;&lol4;&lol4;&lol4;&lol4;&lol4;&lol4;&lol4;&lol4;&lol4;">
+  <!ENTITY lol6 "&lol5;&lol5;&lol5;&lol5;&lol5;&lol5;&lol5;&lol5;&lol5;&lol5;">
+  <!ENTITY lol7 "&lol6;&lol6;&lol6;&lol6;&lol6;&lol6;&lol6;&lol6;&lol6;&lol6;">
+  <!ENTITY lol8 "&lol7;&lol7;&lol7;&lol7;&lol7;&lol7;&lol7;&lol7;&lol7;&lol7;">
+  <!ENTITY lol9 "&lol8;&lol8;&lol8;&lol8;&lol8;&lol8;&lol8;&lol8;&lol8;&lol8;">
+]>
+<lolz>&lol9;</lolz>"#;
+
+    assert!(matches!(
+        xml_to_json(laughs),
+        Err(Error::XmlParseEntityLimitExceeded)
+    ));
+
+    assert!(matches!(
+        xml_to_json_with_options(
+            "<!DOCTYPE root [<!ENTITY a \"0123456789\">]><root>&a;&a;</root>",
+            &ReadOptions {
+                limits: ParseLimits {
+                    max_entity_expansion: Some(15),
+                    ..Default::default()
+                },
+                ..Default::default()
+            }
+        ),
+        Err(Error::XmlParseEntityLimitExceeded)
+    ));
+}
+
+#[test]
+fn c14n_spec_entity_references() {
+    // Canonical XML 1.0, section 3.5, with the external entity replaced by
+    // an internal one since jxon never fetches external entities.
+    let xml = r#"<!DOCTYPE doc [
+<!ATTLIST doc attrExtEnt ENTITY #IMPLIED>
+<!ENTITY ent1 "Hello">
+<!ENTITY ent2 "world">
+<!ENTITY entExt SYSTEM "earth.gif" NDATA gif>
+<!NOTATION gif SYSTEM "viewgif.exe">
+]>
+<doc attrExtEnt="entExt">
+   &ent1;, &ent2;!
+</doc>
+
+<!-- Let world.txt contain "world" (excluding the quotes) -->"#;
+
+    assert_eq!(
+        canonicalize_xml(xml, &CanonicalOptions::default()).unwrap(),
+        r#"<doc attrExtEnt="entExt">
+   Hello, world!
+</doc>"#
+    );
+}
+
+#[test]
+fn lenient() {
+    let xml = "<feed><title>Q&A & more &nbsp;</title><entry><b>bold</i></entry></feed></feed><entry id=\"1&2\"><p>cut";
+
+    assert!(matches!(
+        xml_to_json(xml),
+        Err(Error::XmlParseInvalidEntity(_))
+    ));
+
+    let (value, diagnostics) = xml_to_json_lenient(xml, &ReadOptions::default()).unwrap();
+
+    assert_eq!(
+        value,
+        json!({
+            "feed": [{
+                "title": [{ "_": "Q&A & more &nbsp;" }],
+                "entry": [{ "b": [{ "_": "bold" }] }]
+            }],
+            "entry": [{
+                "p": [{ "_": "cut" }],
+                "$id": "1&2"
+            }]
+        })
+    );
+
+    let diagnostics = diagnostics
+        .iter()
+        .map(|d| (d.position, d.error.to_string()))
+        .collect::<Vec<_>>();
+
+    assert_eq!(
+        diagnostics,
+        vec![
+            (
+                14,
+                "xml parse error: invalid entity reference &A".to_owned()
+            ),
+            (17, "xml parse error: invalid entity reference &".to_owned()),
+            (24, "xml parse error: unknown entity &nbsp;".to_owned()),
+            (52, "xml parse error: unexpected end tag </i>".to_owned()),
+            (56, "xml parse error: element <b> is not closed".to_owned()),
+            (71, "xml parse error: unexpected end tag </feed>".to_owned()),
+            (
+                90,
+                "xml parse error: invalid entity reference &2".to_owned()
+            ),
+            (99, "xml parse error: element <p> is not closed".to_owned()),
+            (
+                99,
+                "xml parse error: element <entry> is not closed".to_owned()
+            ),
+        ]
+    );
+
+    let (value, diagnostics) =
+        xml_to_json_lenient("<a><b><c>text</a>", &ReadOptions::default()).unwrap();
+
+    assert_eq!(
+        value,
+        json!({ "a": [{ "b": [{ "c": [{ "_": "text" }] }] }] })
+    );
+    assert!(matches!(
+        diagnostics.as_slice(),
+        [
+            Diagnostic { position: 13, error: Error::XmlParseUnclosedElement(c) },
+            Diagnostic { position: 13, error: Error::XmlParseUnclosedElement(b) },
+        ] if c == "c" && b == "b"
+    ));
+
+    let (value, diagnostics) = xml_to_json_lenient("<a>x</a>", &ReadOptions::default()).unwrap();
+
+    assert_eq!(value, json!({ "a": [{ "_": "x" }] }));
+    assert!(diagnostics.is_empty());
+}
+
+#[test]
+fn html() {
+    let options = ReadOptions {
+        html: true,
+        ..Default::default()
+    };
+    let html = r#"<DIV Class=intro>Fish &amp; chips&nbsp;&mdash; &hellip; AT&T<BR>
+<img src=logo.png alt="Logo" hidden></img><input type=checkbox checked/>
+<P>&notanentity; &copy 2024</p></Div>"#;
+
+    let value = xml_to_json_with_options(html, &options).unwrap();
+
+    assert_eq!(
+        value,
+        json!({
+            "div": [{
+                "_": "Fish & chips\u{a0}\u{2014} \u{2026} AT&T",
+                "br": [{}],
+                "img": [{
+                    "$src": "logo.png",
+                    "$alt": "Logo",
+                    "$hidden": ""
+                }],
+                "input": [{
+                    "$type": "checkbox",
+                    "$checked": ""
+                }],
+                "p": [{ "_": "&notanentity; &copy 2024" }],
+                "$class": "intro"
+            }]
+        })
+    );
+
+    assert_eq!(
+        json_to_xml(
+            &value.to_string(),
+            &WriteOptions {
+                html: true,
+                ..Default::default()
+            }
+        )
+        .unwrap(),
+        "<div class=\"intro\">Fish &amp; chips\u{a0}\u{2014} \u{2026} AT&amp;T<br>\
+         <img src=\"logo.png\" alt=\"Logo\" hidden=\"\"><input type=\"checkbox\" checked=\"\">\
+         <p>&amp;notanentity; &amp;copy 2024</p></div>"
+    );
+    assert_eq!(
+        json_to_xml(
+            r#"{"p":[{"br":[{}],"span":[{}]}]}"#,
+            &WriteOptions {
+                html: true,
+                ..Default::default()
+            }
+        )
+        .unwrap(),
+        "<p><br><span></span></p>"
+    );
+
+    assert!(matches!(
+        xml_to_json_with_options("<ul><li>one<li>two</ul>", &options),
+        Err(Error::XmlParseUnclosedElement(ref name)) if name == "li"
+    ));
+
+    let (value, diagnostics) =
+        xml_to_json_lenient("<UL><li>one</LI><li>two</ul>", &options).unwrap();
+
+    assert_eq!(
+        value,
+        json!({ "ul": [{ "li": [{ "_": "one" }, { "_": "two" }] }] })
+    );
+    assert_eq!(diagnostics.len(), 1);
+
+    assert!(xml_to_json("<br>").is_err());
+}
+
+#[test]
+fn fragments() {
+    let xml = r#"<?xml version="1.0"?><b>1</b>text<a/><b x="2"/>"#;
+
+    assert_eq!(
+        xml_fragments_to_json(xml, &ReadOptions::default()).unwrap(),
+        vec![
+            json!({ "#": { "version": "1.0" } }),
+            json!({ "b": [{ "_": "1" }] }),
+            json!({ "_": "text" }),
+            json!({ "a": [{}] }),
+            json!({ "b": [{ "$x": "2" }] }),
+        ]
+    );
+    assert_eq!(
+        xml_fragments_to_json("", &ReadOptions::default()).unwrap(),
+        Vec::<Value>::new()
+    );
+    assert!(matches!(
+        xml_fragments_to_json("<a/><b>", &ReadOptions::default()),
+        Err(Error::XmlParseUnexpectedEof)
+    ));
+
+    let stream = r#"<?xml version="1.0"?>
+<!DOCTYPE log [<!ENTITY app "jxon">]>
+<log level="info">&app; started</log>
+<?xml version="1.0" encoding="UTF-8"?>
+<log level="warn">low disk</log>
+<!-- no declaration -->
+<log level="info"><empty/></log>
+"#;
+    let documents = xml_documents_to_json(stream.as_bytes(), &ReadOptions::default())
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+
+    assert_eq!(
+        documents,
+        vec![
+            json!({
+                "#": { "version": "1.0" },
+                "log": [{ "_": "jxon started", "$level": "info" }]
+            }),
+            json!({
+                "#": { "version": "1.0", "encoding": "UTF-8" },
+                "log": [{ "_": "low disk", "$level": "warn" }]
+            }),
+            json!({
+                "log": [{ "empty": [{}], "$level": "info" }]
+            }),
+        ]
+    );
+
+    let options = ReadOptions {
+        limits: ParseLimits {
+            max_elements: Some(1),
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let mut documents = xml_documents_to_json(&b"<a/><b>&app;</b><c/>"[..], &options);
+
+    assert_eq!(documents.next().unwrap().unwrap(), json!({ "a": [{}] }));
+    assert!(matches!(
+        documents.next(),
+        Some(Err(Error::XmlParseUnknownEntity(_)))
+    ));
+    assert!(documents.next().is_none());
+
+    let options = ReadOptions {
+        whitespace: Whitespace::Preserve,
+        ..Default::default()
+    };
+    let documents = xml_documents_to_json(&b"<a/>\n<b/>\n"[..], &options)
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+
+    assert_eq!(documents, vec![json!({ "a": [{}] }), json!({ "b": [{}] })]);
+}
+
+#[test]
+fn records_at_path() {
+    let xml = r#"<?xml version="1.0"?>
+<!DOCTYPE feed [<!ENTITY site "example.com">]>
+<feed lang="en">
+  <title>Feed</title>
+  <entry id="1"><title>One</title><link href="https://&site;/1"/></entry>
+  <other><entry id="skipped"/></other>
+  <entry id="2"/>
+  <entry id="3"><entry id="nested"/></entry>
+</feed>"#;
+    let mut entries = records(xml.as_bytes(), "/feed/entry");
+
+    assert_eq!(
+        entries.next().unwrap().unwrap(),
+        json!({
+            "entry": [{
+                "title": [{ "_": "One" }],
+                "link": [{ "$href": "https://example.com/1" }],
+                "$id": "1"
+            }]
+        })
+    );
+
+    let ancestors = entries.ancestors().collect::<Vec<_>>();
+    assert_eq!(ancestors.len(), 1);
+    assert_eq!(ancestors[0].0, "feed");
+    assert_eq!(
+        Value::Object(ancestors[0].1.clone()),
+        json!({ "$lang": "en" })
+    );
+
+    assert_eq!(
+        entries.collect::<Result<Vec<_>, _>>().unwrap(),
+        vec![
+            json!({ "entry": [{ "$id": "2" }] }),
+            json!({ "entry": [{ "entry": [{ "$id": "nested" }], "$id": "3" }] }),
+        ]
+    );
+
+    assert_eq!(records(xml.as_bytes(), "/rss/channel/item").count(), 0);
+
+    let mut invalid = records(xml.as_bytes(), "feed/entry");
+    assert!(matches!(
+        invalid.next(),
+        Some(Err(Error::XmlInvalidPath(_)))
+    ));
+    assert!(invalid.next().is_none());
+
+    let mut truncated = records(&b"<feed><entry/><entry>"[..], "/feed/entry");
+    assert!(matches!(truncated.next(), Some(Ok(_))));
+    assert!(matches!(
+        truncated.next(),
+        Some(Err(Error::XmlParseUnexpectedEof))
+    ));
+    assert!(truncated.next().is_none());
+
+    // The limits apply to each record, so a long feed of small entries
+    // stays within them.
+    let count = 10_000;
+    let mut long = String::from("<feed>");
+    for i in 0..count {
+        long.push_str(&format!("<entry><n>{}</n></entry>", i));
+    }
+    long.push_str("</feed>");
+
+    let options = ReadOptions {
+        limits: ParseLimits {
+            max_elements: Some(2),
+            max_bytes: Some(64),
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let mut read = 0;
+
+    for (i, record) in records_with_options(long.as_bytes(), "/feed/entry", &options).enumerate() {
+        assert_eq!(
+            record.unwrap(),
+            json!({ "entry": [{ "n": [{ "_": i.to_string() }] }] })
+        );
+        read += 1;
+    }
+
+    assert_eq!(read, count);
+}
+
+#[test]
+fn projection() {
+    let xml = r#"<feed lang="en">
+  <title>Feed</title>
+  <entry id="1">
+    <title>One</title>
+    <author><name>Ann</name><email>ann@example.com</email></author>
+    <content>Long text</content>
+  </entry>
+  <entry id="2">
+    <title>Two</title>
+    <content><section><link href="/a"/></section></content>
+  </entry>
+</feed>"#;
+    let project = |include: &[&str], exclude: &[&str]| {
+        xml_to_json_with_options(
+            xml,
+            &ReadOptions {
+                include: include.iter().map(|p| p.to_string()).collect(),
+                exclude: exclude.iter().map(|p| p.to_string()).collect(),
+                ..Default::default()
+            },
+        )
+        .unwrap()
+    };
+
+    assert_eq!(
+        project(&["/feed/entry/title"], &[]),
+        json!({
+            "feed": [{
+                "entry": [
+                    { "title": [{ "_": "One" }] },
+                    { "title": [{ "_": "Two" }] }
+                ]
+            }]
+        })
+    );
+    assert_eq!(
+        project(&["/*/*/author"], &["/feed/entry/author/email"]),
+        json!({
+            "feed": [{
+                "entry": [{ "author": [{ "name": [{ "_": "Ann" }] }] }]
+            }]
+        })
+    );
+    assert_eq!(
+        project(&["/**/link", "/feed/title"], &[]),
+        json!({
+            "feed": [{
+                "title": [{ "_": "Feed" }],
+                "entry": [{
+                    "content": [{ "section": [{ "link": [{ "$href": "/a" }] }] }]
+                }]
+            }]
+        })
+    );
+    assert_eq!(
+        project(&[], &["/feed/entry/**/content", "/feed/title"]),
+        json!({
+            "feed": [{
+                "entry": [
+                    {
+                        "title": [{ "_": "One" }],
+                        "author": [{
+                            "name": [{ "_": "Ann" }],
+                            "email": [{ "_": "ann@example.com" }]
+                        }],
+                        "$id": "1"
+                    },
+                    { "title": [{ "_": "Two" }], "$id": "2" }
+                ],
+                "$lang": "en"
+            }]
+        })
+    );
+    assert_eq!(project(&["/rss"], &[]), json!({}));
+
+    let options = ReadOptions {
+        exclude: vec!["/feed/entry/content".to_owned()],
+        ..Default::default()
+    };
+    assert_eq!(
+        records_with_options(xml.as_bytes(), "/feed/entry", &options)
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap()[1],
+        json!({ "entry": [{ "title": [{ "_": "Two" }], "$id": "2" }] })
+    );
+
+    let options = ReadOptions {
+        include: vec!["feed".to_owned()],
+        ..Default::default()
+    };
+    assert!(matches!(
+        xml_to_json_with_options(xml, &options),
+        Err(Error::XmlInvalidPath(ref path)) if path == "feed"
+    ));
+}
+
+#[test]
+fn json_writer() {
+    let xml = r#"<?xml version="1.0"?>
+<list id="1">
+  <item>a</item>
+  <item n="2">b</item>
+  <other/>
+  <item>c &amp; d</item>
+</list>"#;
+    let mut out = vec![];
+    xml_to_json_writer_with_options(xml.as_bytes(), &mut out, &ReadOptions::default()).unwrap();
+    assert_eq!(
+        serde_json::from_slice::<Value>(&out).unwrap(),
+        xml_to_json(xml).unwrap()
+    );
+
+    let mut out = vec![];
+    xml_to_json_writer(xml.as_bytes(), &mut out).unwrap();
+    let out = String::from_utf8(out).unwrap();
+    assert_eq!(
+        out,
+        concat!(
+            r##"{"#":{"version":"1.0"},"list":[{"$id":"1","##,
+            r#""item":[{"_":"a"},{"$n":"2","_":"b"}],"other":[{}],"item":[{"_":"c & d"}]}]}"#
+        )
+    );
+
+    // Only the streaming writer reads siblings that are not adjacent back.
+    let mut xml = vec![];
+    json_to_xml_writer(out.as_bytes(), &mut xml, &WriteOptions::default()).unwrap();
+    assert_eq!(
+        String::from_utf8(xml).unwrap(),
+        r#"<?xml version="1.0"?><list id="1"><item>a</item><item n="2">b</item><other/><item>c &amp; d</item></list>"#
+    );
+    assert!(matches!(
+        json_to_xml(&out, &WriteOptions::default()),
+        Err(Error::JsonParseError(ref e)) if e.to_string().contains("duplicate key \"item\"")
+    ));
+    assert!(matches!(
+        json_to_xml_bytes(&out, &WriteOptions::default()),
+        Err(Error::JsonParseError(_))
+    ));
+
+    let options = ReadOptions {
+        document_order: true,
+        include: vec!["/list/item".to_owned()],
+        exclude: vec!["/list/item/b".to_owned()],
+        preserve_style: true,
+        ..Default::default()
+    };
+    let mut out = vec![];
+    xml_to_json_writer_with_options(
+        "<list><x><y/></x><item><b>no</b></item></list>".as_bytes(),
+        &mut out,
+        &options,
+    )
+    .unwrap();
+    assert_eq!(
+        String::from_utf8(out).unwrap(),
+        r##"{"list":[{"item":[{"#empty":"expanded"}]}]}"##
+    );
+
+    let mut out = vec![];
+    assert!(matches!(
+        xml_to_json_writer_with_options("<a><b></a>".as_bytes(), &mut out, &options),
+        Err(Error::XmlQuickXmlError(_))
+    ));
+}
+
+#[test]
+fn xml_writer() {
+    let json = r##"{"#":{"version":"1.0"},"root":[{"$id":"1","#quotes":"'","item":[{"_":"a &"},{"$n":"2"}],"x":[{"#empty":"expanded"}]}]}"##;
+    let options = WriteOptions::default();
+    let mut out = vec![];
+    json_to_xml_writer(json.as_bytes(), &mut out, &options).unwrap();
+    assert_eq!(
+        String::from_utf8(out).unwrap(),
+        json_to_xml(json, &options).unwrap()
+    );
+
+    let xml = "<list a=\"1\"><item>x</item><item><b/></item>tail</list>";
+    let read_options = ReadOptions {
+        document_order: true,
+        ..Default::default()
+    };
+    let mut json = vec![];
+    xml_to_json_writer_with_options(xml.as_bytes(), &mut json, &read_options).unwrap();
+    let mut out = vec![];
+    json_to_xml_writer(json.as_slice(), &mut out, &options).unwrap();
+    assert_eq!(String::from_utf8(out).unwrap(), xml);
+
+    let xml = r#"<a x="1"><b y="2"/>t<c><d z="3">u</d></c></a>"#;
+    let json = xml_to_json(xml).unwrap().to_string();
+    assert_eq!(
+        json,
+        r#"{"a":[{"$x":"1","_":"t","b":[{"$y":"2"}],"c":[{"d":[{"$z":"3","_":"u"}]}]}]}"#
+    );
+    let mut out = vec![];
+    json_to_xml_writer(json.as_bytes(), &mut out, &options).unwrap();
+    assert_eq!(
+        String::from_utf8(out).unwrap(),
+        r#"<a x="1">t<b y="2"/><c><d z="3">u</d></c></a>"#
+    );
+
+    // Text keeps indentation out of its element however deep it is.
+    let indented = WriteOptions {
+        indent: Some(Indent {
+            character: b' ',
+            size: 2,
+        }),
+        ..Default::default()
+    };
+    let json = xml_to_json("<r><a><b/>t<c><d/></c></a><e><f/></e></r>")
+        .unwrap()
+        .to_string();
+    let mut out = vec![];
+    json_to_xml_writer(json.as_bytes(), &mut out, &indented).unwrap();
+    let out = String::from_utf8(out).unwrap();
+    assert_eq!(out, json_to_xml(&json, &indented).unwrap());
+    assert_eq!(
+        out,
+        "<r>\n  <a>t<b/><c><d/></c></a>\n  <e>\n    <f/>\n  </e>\n</r>"
+    );
+    assert!(matches!(
+        json_to_xml_writer(
+            r#"{"a":[{"b":[{}],"_":"t"}]}"#.as_bytes(),
+            vec![],
+            &indented
+        ),
+        Err(Error::JsonParseTextAfterIndentedContent)
+    ));
+    assert!(matches!(
+        json_to_xml_writer(r#"{"a":[{"b":[{}],"_":"t"}]}"#.as_bytes(), vec![], &options),
+        Ok(())
+    ));
+
+    let depth = 1000;
+    let json = format!("{}{{}}{}", r#"{"a":["#.repeat(depth), "]}".repeat(depth));
+    let mut out = vec![];
+    json_to_xml_writer(json.as_bytes(), &mut out, &options).unwrap();
+    assert_eq!(
+        String::from_utf8(out).unwrap(),
+        format!(
+            "{}<a/>{}",
+            "<a>".repeat(depth - 1),
+            "</a>".repeat(depth - 1)
+        )
+    );
+
+    let write = |json: &str| json_to_xml_writer(json.as_bytes(), vec![], &options);
+    assert!(matches!(write(r#"{"a":[{"b":[]}],"$x":"1"}"#), Ok(())));
+    assert!(matches!(
+        write(r#"{"a":[{"b":[],"$x":"1"}]}"#),
+        Err(Error::JsonParseAttributeAfterContent)
+    ));
+    assert!(matches!(
+        write(r#"{"a":[1]}"#),
+        Err(Error::JsonParseExpectedObject)
+    ));
+    assert!(matches!(
+        write(r#"{"a":{}}"#),
+        Err(Error::JsonParseExpectedArray)
+    ));
+    assert!(matches!(write("[]"), Err(Error::JsonParseUnexpectedArray)));
+    assert!(matches!(
+        write(r#"{"a":[{"$x":1}]}"#),
+        Err(Error::JsonParseInvalidAttributeValue)
+    ));
+    assert!(matches!(
+        write(r#"{"a":[{}]} x"#),
+        Err(Error::JsonParseError(_))
+    ));
+}
+
+#[test]
+fn push_parser() {
+    let stream = concat!(
+        r#"<?xml version="1.0"?><stream:stream to="example.com">"#,
+        r#"<message id="1" note="a > b"><body>hi &amp; bye</body><!-- > --></message>"#,
+        r#"<presence/><iq><![CDATA[</iq>]]></iq>"#
+    );
+    let mut parser = JxonParser::new(2);
+    let mut stanzas = vec![];
+
+    for chunk in stream.as_bytes().chunks(3) {
+        stanzas.extend(parser.feed(chunk).unwrap());
+    }
+
+    assert_eq!(
+        stanzas,
+        vec![
+            json!({
+                "message": [{
+                    "body": [{ "_": "hi & bye" }],
+                    "$id": "1",
+                    "$note": "a > b"
+                }]
+            }),
+            json!({ "presence": [{}] }),
+            json!({ "iq": [{}] }),
+        ]
+    );
+    assert_eq!(
+        parser.ancestors().collect::<Vec<_>>(),
+        vec![(
+            "stream:stream",
+            json!({ "$to": "example.com" }).as_object().unwrap()
+        )]
+    );
+    assert!(parser.feed(b"</stream:stream>").unwrap().is_empty());
+    parser.finish().unwrap();
+
+    let mut parser = JxonParser::new(1);
+    assert_eq!(
+        parser.feed(b"<a>1</a><b>").unwrap(),
+        vec![json!({ "a": [{ "_": "1" }] })]
+    );
+    assert_eq!(
+        parser.feed(b"2</b><c").unwrap(),
+        vec![json!({ "b": [{ "_": "2" }] })]
+    );
+    assert!(matches!(parser.finish(), Err(Error::XmlParseUnexpectedEof)));
+
+    let mut parser = JxonParser::new(1);
+    assert_eq!(
+        parser
+            .feed(b"<!DOCTYPE a [<!-- it's ] --><?pi \"?>]><a/>")
+            .unwrap(),
+        vec![json!({ "a": [{}] })]
+    );
+    parser.finish().unwrap();
+
+    let options = ReadOptions {
+        limits: ParseLimits {
+            max_bytes: Some(16),
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let mut parser = JxonParser::with_options(1, &options);
+    assert!(parser.feed(b"<a>short</a>").is_ok());
+    assert!(matches!(
+        parser.feed(b"<a>never closed ..."),
+        Err(Error::XmlParseSizeLimitExceeded)
+    ));
+}
+
+#[test]
+fn structural_events() {
+    let xml = r#"<?xml version="1.0"?><feed lang="en"><entry id="1">One</entry><!-- note --><br/></feed>"#;
+    let start = |name: &str, attributes: Value| JxonEvent::StartElement {
+        name: name.to_owned(),
+        attributes: attributes.as_object().unwrap().clone(),
+    };
+    let end = |name: &str| JxonEvent::EndElement {
+        name: name.to_owned(),
+    };
+
+    assert_eq!(
+        events(xml.as_bytes())
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap(),
+        vec![
+            JxonEvent::Decl(json!({ "version": "1.0" }).as_object().unwrap().clone()),
+            start("feed", json!({ "$lang": "en" })),
+            start("entry", json!({ "$id": "1" })),
+            JxonEvent::Text("One".to_owned()),
+            end("entry"),
+            start("br", json!({})),
+            end("br"),
+            end("feed"),
+        ]
+    );
+
+    // An element on the way to those a projection includes starts without
+    // its attributes once one of them turns up, and not at all otherwise.
+    let options = ReadOptions {
+        include: vec!["/feed/entry/b".to_owned()],
+        ..ReadOptions::default()
+    };
+    let xml = r#"<feed lang="en"><entry><a/></entry><entry id="2">x<b/></entry></feed>"#;
+    assert_eq!(
+        events_with_options(xml.as_bytes(), &options)
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap(),
+        vec![
+            start("feed", json!({})),
+            start("entry", json!({})),
+            start("b", json!({})),
+            end("b"),
+            end("entry"),
+            end("feed"),
+        ]
+    );
+
+    let mut truncated = events(&b"<a>text"[..]);
+    assert_eq!(truncated.next().unwrap().unwrap(), start("a", json!({})));
+    assert_eq!(
+        truncated.next().unwrap().unwrap(),
+        JxonEvent::Text("text".to_owned())
+    );
+    assert!(matches!(
+        truncated.next(),
+        Some(Err(Error::XmlParseUnexpectedEof))
+    ));
+    assert!(truncated.next().is_none());
+}
+
+#[test]
+fn value_to_events() {
+    use quick_xml::{
+        events::{BytesEnd, BytesStart, Event},
+        Writer,
+    };
+
+    let value = json!({
+        "Body": [{
+            "item": [{ "$id": "1", "_": "a & b" }, { "$id": "2" }],
+            "#quotes": "'"
+        }]
+    });
+    let mut writer = Writer::new(vec![]);
+    writer
+        .write_event(Event::Start(BytesStart::borrowed_name(b"Envelope")))
+        .unwrap();
+
+    for event in to_events(&value) {
+        writer.write_event(event.unwrap()).unwrap();
+    }
+
+    writer
+        .write_event(Event::End(BytesEnd::borrowed(b"Envelope")))
+        .unwrap();
+    assert_eq!(
+        String::from_utf8(writer.into_inner()).unwrap(),
+        r#"<Envelope><Body><item id="1">a &amp; b</item><item id="2"/></Body></Envelope>"#
+    );
+
+    let invalid = json!({ "a": [{ "b": 1 }] });
+    let mut invalid = to_events(&invalid);
+    assert!(matches!(invalid.next(), Some(Ok(Event::Start(_)))));
+    assert!(matches!(
+        invalid.next(),
+        Some(Err(Error::JsonParseExpectedArray))
+    ));
+    assert!(invalid.next().is_none());
+}
+
+#[test]
+fn element_from_reader() {
+    use quick_xml::{events::Event, Reader};
+
+    let xml = r#"<Envelope><Header/><Body><order id="7"><item>a</item><item>b</item></order></Body></Envelope>"#;
+    let mut reader = Reader::from_str(xml);
+    let (mut buf, mut inner) = (vec![], vec![]);
+    let mut names = vec![];
+    let mut order = None;
+
+    loop {
+        match reader.read_event(&mut buf).unwrap() {
+            Event::Start(e) if e.name() == b"order" => {
+                order = Some(parse_element(&mut reader, &e, &mut inner).unwrap());
+            }
+            Event::Start(e) => names.push(format!("<{}>", String::from_utf8_lossy(e.name()))),
+            Event::Empty(e) => names.push(format!("<{}/>", String::from_utf8_lossy(e.name()))),
+            Event::End(e) => names.push(format!("</{}>", String::from_utf8_lossy(e.name()))),
+            Event::Eof => break,
+            _ => {}
+        }
+
+        buf.clear();
+    }
+
+    assert_eq!(
+        order.unwrap(),
+        json!({ "order": [{ "item": [{ "_": "a" }, { "_": "b" }], "$id": "7" }] })
+    );
+    assert_eq!(
+        names,
+        vec![
+            "<Envelope>",
+            "<Header/>",
+            "<Body>",
+            "</Body>",
+            "</Envelope>"
+        ]
+    );
+
+    // An end tag that does not match is an error, as the reader checks them.
+    let mut reader = Reader::from_str("<a><b></a>");
+    let mut buf = vec![];
+    let start = loop {
+        if let Event::Start(e) = reader.read_event(&mut buf).unwrap() {
+            break e.into_owned();
+        }
+    };
+    assert!(matches!(
+        parse_element(&mut reader, &start, &mut buf),
+        Err(Error::XmlQuickXmlError(_))
+    ));
+
+    // An empty element tag does not take in what follows it.
+    let mut reader = Reader::from_str("<env><body/><x/></env>");
+    let mut buf = vec![];
+    let start = loop {
+        if let Event::Empty(e) = reader.read_event(&mut buf).unwrap() {
+            break e.into_owned();
+        }
+    };
+    assert!(matches!(
+        parse_element(&mut reader, &start, &mut buf),
+        Err(Error::XmlParseUnexpectedEndTag(name)) if name == "env"
+    ));
+}
+
+#[test]
+fn document_tree() {
+    let xml = r#"<?xml version="1.0"?><!DOCTYPE feed [<!ENTITY site "example.com">]><!-- top --><feed lang="en"><title>A &amp; &site;</title><?render fast?><note><![CDATA[<raw>]]></note><title>B</title><empty/></feed>"#;
+    let document = Document::parse(xml).unwrap();
+    let feed = document.root().unwrap();
+
+    assert_eq!(document.declaration.as_ref().unwrap().version, "1.0");
+    assert_eq!(feed.attribute("lang"), Some("en"));
+    assert_eq!(
+        feed.children[0],
+        Node::Element(Element {
+            name: "title".to_owned(),
+            attributes: vec![],
+            children: vec![Node::Text("A & example.com".to_owned())],
+        })
+    );
+    assert_eq!(feed.children[1], Node::PI("render fast".to_owned()));
+    assert_eq!(
+        feed.elements().map(|e| e.name.as_str()).collect::<Vec<_>>(),
+        vec!["title", "note", "title", "empty"]
+    );
+    assert_eq!(
+        document.to_xml(&WriteOptions::default()).unwrap(),
+        xml.replace("&site;", "example.com")
+    );
+
+    let value = document.to_value().unwrap();
+    assert_eq!(value, xml_to_json(xml).unwrap());
+    assert_eq!(
+        value,
+        json!({
+            "#": { "version": "1.0" },
+            "feed": [{
+                "title": [{ "_": "A & example.com" }, { "_": "B" }],
+                "note": [{}],
+                "empty": [{}],
+                "$lang": "en"
+            }]
+        })
+    );
+    assert_eq!(
+        Document::from_value(&value).unwrap().to_value().unwrap(),
+        value
+    );
+
+    let built = Document {
+        children: vec![Node::Element(Element {
+            name: "a".to_owned(),
+            attributes: vec![("b".to_owned(), "\"x\"".to_owned())],
+            children: vec![Node::Comment(" c ".to_owned()), Node::Text("<".to_owned())],
+        })],
+        ..Document::default()
+    };
+    let xml = built.to_xml(&WriteOptions::default()).unwrap();
+    assert_eq!(xml, r#"<a b="&quot;x&quot;"><!-- c -->&lt;</a>"#);
+    assert_eq!(Document::parse(&xml).unwrap(), built);
+
+    assert!(matches!(
+        Document::parse("<a><b></b>"),
+        Err(Error::XmlParseUnexpectedEof)
+    ));
+
+    let mut options = ReadOptions::default();
+    options
+        .entities
+        .insert("site".to_owned(), "example.org".to_owned());
+    assert_eq!(
+        Document::parse_with_options("<a>&site;</a>", &options)
+            .unwrap()
+            .to_xml(&WriteOptions::default())
+            .unwrap(),
+        "<a>example.org</a>"
+    );
+    options.limits.max_depth = Some(2);
+    assert!(Document::parse_with_options("<a><b/></a>", &options).is_ok());
+    assert!(matches!(
+        Document::parse_with_options("<a><b><c/></b></a>", &options),
+        Err(Error::XmlParseDepthLimitExceeded)
+    ));
+    options.limits.max_text_length = Some(3);
+    assert!(matches!(
+        Document::parse_with_options("<a>&site;</a>", &options),
+        Err(Error::XmlParseTextLimitExceeded)
+    ));
+    options.limits.max_bytes = Some(8);
+    assert!(matches!(
+        Document::parse_with_options("<a>b</a><!---->", &options),
+        Err(Error::XmlParseSizeLimitExceeded)
+    ));
+}
+
+#[test]
+fn element_view() {
+    let value = xml_to_json(
+        r#"<?xml version="1.0"?><feed count="2" size="big"><entry id="1"><title>One</title></entry><entry id="x"/><link/></feed>"#,
+    )
+    .unwrap();
+    let document = ElementRef::new(&value).unwrap();
+    let feed = document.child("feed").unwrap();
+
+    assert_eq!(document.name(), None);
+    assert_eq!(feed.name(), Some("feed"));
+    assert_eq!(feed.parse_attr::<u32>("count"), Some(Ok(2)));
+    assert!(matches!(feed.parse_attr::<u32>("size"), Some(Err(_))));
+    assert_eq!(feed.parse_attr::<u32>("missing"), None);
+    assert_eq!(
+        feed.attrs().collect::<Vec<_>>(),
+        vec![("count", "2"), ("size", "big")]
+    );
+    assert_eq!(
+        feed.children("entry")
+            .map(|entry| entry.attr("id"))
+            .collect::<Vec<_>>(),
+        vec![Some("1"), Some("x")]
+    );
+    assert_eq!(
+        feed.child("entry")
+            .and_then(|entry| entry.child("title"))
+            .and_then(|title| title.text()),
+        Some("One")
+    );
+    assert_eq!(feed.child("title"), None);
+    assert_eq!(feed.text(), None);
+    assert_eq!(document.child("#"), None);
+    assert_eq!(
+        document
+            .descendants()
+            .map(|element| element.name().unwrap())
+            .collect::<Vec<_>>(),
+        vec!["feed", "entry", "title", "entry", "link"]
+    );
+    assert_eq!(ElementRef::new(&json!([1])), None);
+
+    let value = json!({ "#": {}, "#empty": "expanded", "#x": [{ "_": "y" }], "_": "z" });
+    let element = ElementRef::new(&value).unwrap();
+    assert_eq!(
+        element
+            .elements()
+            .map(|element| element.name().unwrap())
+            .collect::<Vec<_>>(),
+        vec!["#x"]
+    );
+    assert_eq!(element.text(), Some("z"));
+}
+
+#[test]
+fn xml_macro() {
+    let id = 7;
+    let text = String::from("a < b");
+    let value = xml!(
+        <feed id={id} lang="en">
+            <entry><title>{text}</title><count>{id + 1}</count></entry>
+            <entry id="x">"plain"</entry>
+            <link/>
+        </feed>
+    );
+
+    assert_eq!(
+        value,
+        json!({
+            "feed": [{
+                "entry": [
+                    { "title": [{ "_": "a < b" }], "count": [{ "_": "8" }] },
+                    { "_": "plain", "$id": "x" }
+                ],
+                "link": [{}],
+                "$id": "7",
+                "$lang": "en"
+            }]
+        })
+    );
+    assert_eq!(
+        json_to_xml(&value.to_string(), &WriteOptions::default()).unwrap(),
+        r#"<feed id="7" lang="en"><entry><title>a &lt; b</title><count>8</count></entry><entry id="x">plain</entry><link/></feed>"#
+    );
+
+    let value = xml!(
+        <soap:Envelope xml:lang="en" data-id={id}>
+            <"soap:Body">"x"<empty-item/>"y"</"soap:Body">
+        </soap:Envelope>
+    );
+    assert_eq!(
+        value,
+        json!({
+            "soap:Envelope": [{
+                "$xml:lang": "en",
+                "$data-id": "7",
+                "soap:Body": [{ "_": "xy", "empty-item": [{}] }]
+            }]
+        })
+    );
+}
+
+#[cfg(feature = "tokio")]
+#[tokio::test]
+async fn async_conversions() {
+    use tokio::io::{duplex, AsyncReadExt, AsyncWriteExt};
+
+    let xml = r#"<feed><entry id="1">a</entry><entry id="2">b</entry></feed>"#;
+    let send = |bytes: &'static [u8]| {
+        let (mut input, reader) = duplex(8);
+        tokio::spawn(async move { input.write_all(bytes).await });
+        reader
+    };
+    let receive = || {
+        let (writer, mut output) = duplex(8);
+        let text = tokio::spawn(async move {
+            let mut text = String::new();
+            output.read_to_string(&mut text).await.unwrap();
+            text
+        });
+        (writer, text)
+    };
+
+    let mut records = records_async(send(xml.as_bytes()), "/feed/entry");
+    let mut values = vec![];
+    while let Some(value) = records.next().await {
+        values.push(value.unwrap());
+    }
+    assert_eq!(
+        values,
+        vec![
+            json!({ "entry": [{ "_": "a", "$id": "1" }] }),
+            json!({ "entry": [{ "_": "b", "$id": "2" }] }),
+        ]
+    );
+
+    let mut documents = xml_documents_to_json_async(send(b"<a/><b>"), &ReadOptions::default());
+    assert_eq!(
+        documents.next().await.unwrap().unwrap(),
+        json!({ "a": [{}] })
+    );
+    assert!(matches!(
+        documents.next().await,
+        Some(Err(Error::XmlParseUnexpectedEof))
+    ));
+    assert!(documents.next().await.is_none());
+
+    let (writer, json) = receive();
+    drop(
+        xml_to_json_writer_async(send(xml.as_bytes()), writer)
+            .await
+            .unwrap(),
+    );
+    let json = json.await.unwrap();
+    assert_eq!(
+        serde_json::from_str::<Value>(&json).unwrap(),
+        xml_to_json(xml).unwrap()
+    );
+
+    let options = WriteOptions::default();
+    let (writer, xml) = receive();
+    drop(
+        json_to_xml_writer_async(
+            send(br#"{"feed":[{"entry":[{"$id":"1","_":"a"},{"_":"b"}]}]}"#),
+            writer,
+            &options,
+        )
+        .await
+        .unwrap(),
+    );
+    assert_eq!(
+        xml.await.unwrap(),
+        r#"<feed><entry id="1">a</entry><entry>b</entry></feed>"#
+    );
+
+    let (writer, _) = receive();
+    assert!(matches!(
+        json_to_xml_writer_async(send(br#"{"a":[1]}"#), writer, &options).await,
+        Err(Error::JsonParseExpectedObject)
+    ));
 }